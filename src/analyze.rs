@@ -0,0 +1,417 @@
+use crate::decoder::RawImage;
+use crate::ihdr::ColorType;
+use crate::png::Png;
+use core::fmt;
+
+/// The outcome of one steganalysis test, with a suspicion score from 0 (clean) to 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub test: String,
+    pub score: f64,
+    pub details: String,
+}
+
+impl Finding {
+    pub fn new(test: &str, score: f64, details: String) -> Finding {
+        Finding {
+            test: test.to_string(),
+            score: score.clamp(0.0, 1.0),
+            details,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:.2}] {}: {}", self.score, self.test, self.details)
+    }
+}
+
+/// Flags unknown and private chunks, data after IEND and ancillary chunks above a size limit
+pub fn chunk_findings(png: &Png, trailing_len: usize, max_ancillary_len: u32) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        if !chunk_type.is_public() {
+            let details = format!("private chunk {} at index {}", chunk_type, index);
+            findings.push(Finding::new("private chunk", 1.0, details));
+        } else if !chunk_type.is_known() {
+            let details = format!("unregistered chunk {} at index {}", chunk_type, index);
+            findings.push(Finding::new("unknown chunk", 0.8, details));
+        }
+
+        if !chunk_type.is_critical() && chunk.length() > max_ancillary_len {
+            let details = format!(
+                "{} at index {} holds {} bytes",
+                chunk_type,
+                index,
+                chunk.length()
+            );
+            findings.push(Finding::new("oversized ancillary chunk", 0.5, details));
+        }
+    }
+
+    let details = format!("{} bytes after IEND", trailing_len);
+    let score = if trailing_len > 0 { 1.0 } else { 0.0 };
+    findings.push(Finding::new("trailing data", score, details));
+
+    findings
+}
+
+/// Runs the chi-square and RS tests on the least-significant bit plane of every color channel
+pub fn lsb_findings(raw_image: &RawImage) -> Vec<Finding> {
+    let header = &raw_image.header;
+    if header.color_type == ColorType::Indexed || header.bit_depth < 8 {
+        let details = format!("not applicable to a {} image", header);
+        return vec![Finding::new("LSB analysis", 0.0, details)];
+    }
+
+    let (names, channels) = match header.color_type {
+        ColorType::Grayscale | ColorType::GrayscaleAlpha => ("y", 1),
+        _ => ("rgb", 3),
+    };
+
+    let mut findings = Vec::new();
+    for (channel, name) in names.chars().enumerate().take(channels) {
+        let samples = channel_samples(raw_image, channel);
+
+        let p_value = chi_square(&samples);
+        let details = format!("channel {} p-value {:.4}", name, p_value);
+        findings.push(Finding::new("chi-square", p_value, details));
+
+        let rate = rs_estimate(&samples);
+        let details = format!(
+            "channel {} estimated embedding rate {:.1}%",
+            name,
+            rate * 100.0
+        );
+        findings.push(Finding::new("RS analysis", rate, details));
+    }
+
+    findings
+}
+
+/// Collects the least-significant byte of every sample in one channel
+fn channel_samples(raw_image: &RawImage, channel: usize) -> Vec<u8> {
+    let header = &raw_image.header;
+    let channels = header.color_type.channels();
+    let sample_bytes = header.bit_depth as usize / 8;
+    let pixel_bytes = channels * sample_bytes;
+
+    raw_image
+        .data
+        .chunks_exact(pixel_bytes)
+        .map(|pixel| pixel[(channel + 1) * sample_bytes - 1])
+        .collect()
+}
+
+/// Westfeld and Pfitzmann's chi-square attack. Embedding random bits evens out the counts of
+/// each pair of values differing only in the LSB, so a p-value near 1 suggests hidden data.
+/// Sequential embedding only fills the start of the image, so the most suspicious of several
+/// leading fractions of the samples is reported.
+pub fn chi_square(samples: &[u8]) -> f64 {
+    [0.1, 0.25, 0.5, 1.0]
+        .iter()
+        .map(|fraction| ((samples.len() as f64 * fraction) as usize).max(1))
+        .filter(|len| *len <= samples.len())
+        .map(|len| chi_square_p_value(&samples[..len]))
+        .fold(0.0, f64::max)
+}
+
+fn chi_square_p_value(samples: &[u8]) -> f64 {
+    let mut histogram = [0u64; 256];
+    for sample in samples {
+        histogram[*sample as usize] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+
+    if categories < 2 {
+        return 0.0;
+    }
+
+    let degrees_of_freedom = (categories - 1) as f64;
+    upper_incomplete_gamma(degrees_of_freedom / 2.0, statistic / 2.0)
+}
+
+/// Fridrich's RS analysis. Counts how often flipping LSBs in groups of samples makes them
+/// more (regular) or less (singular) noisy, for the original and the LSB-inverted signal,
+/// and solves for the fraction of samples that must already carry message bits.
+pub fn rs_estimate(samples: &[u8]) -> f64 {
+    let inverted: Vec<u8> = samples.iter().map(|sample| sample ^ 1).collect();
+
+    let (r_m0, s_m0, r_n0, s_n0) = rs_counts(samples);
+    let (r_m1, s_m1, r_n1, s_n1) = rs_counts(&inverted);
+
+    let d0 = r_m0 - s_m0;
+    let d1 = r_m1 - s_m1;
+    let dn0 = r_n0 - s_n0;
+    let dn1 = r_n1 - s_n1;
+
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+
+    let x = if a.abs() < f64::EPSILON {
+        if b.abs() < f64::EPSILON {
+            return 0.0;
+        }
+        -c / b
+    } else {
+        // Near full embedding the two curves barely differ and rounding can push the
+        // discriminant below zero, where the double root is the best estimate
+        let discriminant = (b * b - 4.0 * a * c).max(0.0);
+        let root1 = (-b + discriminant.sqrt()) / (2.0 * a);
+        let root2 = (-b - discriminant.sqrt()) / (2.0 * a);
+        if root1.abs() < root2.abs() {
+            root1
+        } else {
+            root2
+        }
+    };
+
+    let rate = x / (x - 0.5);
+    if rate.is_finite() {
+        rate.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Relative counts of regular and singular groups under the mask [0, 1, 1, 0] and its negation
+fn rs_counts(samples: &[u8]) -> (f64, f64, f64, f64) {
+    const MASK: [bool; 4] = [false, true, true, false];
+    let smoothness = |group: &[i16]| -> i16 { group.windows(2).map(|w| (w[1] - w[0]).abs()).sum() };
+
+    let mut counts = [0u64; 4];
+    let mut groups = 0u64;
+    for group in samples.chunks_exact(MASK.len()) {
+        let original: Vec<i16> = group.iter().map(|value| *value as i16).collect();
+        let flipped: Vec<i16> = original
+            .iter()
+            .zip(MASK)
+            .map(|(value, masked)| if masked { value ^ 1 } else { *value })
+            .collect();
+        let shifted: Vec<i16> = original
+            .iter()
+            .zip(MASK)
+            .map(|(value, masked)| {
+                if masked {
+                    ((value + 1) ^ 1) - 1
+                } else {
+                    *value
+                }
+            })
+            .collect();
+
+        let before = smoothness(&original);
+        let after_flip = smoothness(&flipped);
+        let after_shift = smoothness(&shifted);
+
+        counts[0] += (after_flip > before) as u64;
+        counts[1] += (after_flip < before) as u64;
+        counts[2] += (after_shift > before) as u64;
+        counts[3] += (after_shift < before) as u64;
+        groups += 1;
+    }
+
+    let groups = groups.max(1) as f64;
+    (
+        counts[0] as f64 / groups,
+        counts[1] as f64 / groups,
+        counts[2] as f64 / groups,
+        counts[3] as f64 / groups,
+    )
+}
+
+/// Regularized upper incomplete gamma function Q(a, x), the chi-square survival function
+/// for a = degrees of freedom / 2 and x = statistic / 2
+fn upper_incomplete_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+
+    if x < a + 1.0 {
+        // Series expansion of the lower function P(a, x)
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        let lower = sum * (-x + a * x.ln() - ln_gamma(a)).exp();
+        (1.0 - lower).clamp(0.0, 1.0)
+    } else {
+        // Lentz's continued fraction for Q(a, x)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        ((-x + a * x.ln() - ln_gamma(a)).exp() * h).clamp(0.0, 1.0)
+    }
+}
+
+/// Lanczos approximation of ln Γ(x) for x > 0
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let mut y = x;
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for coefficient in COEFFICIENTS {
+        y += 1.0;
+        series += coefficient / y;
+    }
+
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::ImageHeader;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use std::str::FromStr;
+
+    /// A smooth, noisy grayscale signal resembling a natural image
+    fn natural_samples(len: usize) -> Vec<u8> {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        (0..len)
+            .map(|i| {
+                let base = 128.0 + 80.0 * (i as f64 / 40.0).sin();
+                (base + rng.gen_range(-3.0..3.0)).round() as u8
+            })
+            .collect()
+    }
+
+    fn with_random_lsbs(samples: &[u8]) -> Vec<u8> {
+        let mut rng = ChaCha20Rng::seed_from_u64(11);
+        samples
+            .iter()
+            .map(|sample| (sample & !1) | rng.gen_range(0..=1))
+            .collect()
+    }
+
+    #[test]
+    fn test_upper_incomplete_gamma() {
+        // Chi-square survival function with 2 degrees of freedom is exp(-x / 2)
+        for statistic in [0.5, 2.0, 10.0] {
+            let expected = (-statistic / 2.0f64).exp();
+            assert!((upper_incomplete_gamma(1.0, statistic / 2.0) - expected).abs() < 1e-9);
+        }
+        assert!((ln_gamma(5.0) - 24.0f64.ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_chi_square_detects_full_embedding() {
+        let samples: Vec<u8> = natural_samples(20000).iter().map(|s| s & !1).collect();
+        assert!(chi_square(&samples) < 0.01);
+        assert!(chi_square(&with_random_lsbs(&samples)) > 0.5);
+    }
+
+    #[test]
+    fn test_rs_estimate() {
+        let samples = natural_samples(20000);
+        assert!(rs_estimate(&samples) < 0.15);
+        assert!(rs_estimate(&with_random_lsbs(&samples)) > 0.5);
+
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let half: Vec<u8> = samples
+            .iter()
+            .map(|sample| {
+                if rng.gen_bool(0.5) {
+                    (sample & !1) | rng.gen_range(0..=1)
+                } else {
+                    *sample
+                }
+            })
+            .collect();
+        let rate = rs_estimate(&half);
+        assert!(rate > 0.3 && rate < 0.7);
+    }
+
+    #[test]
+    fn test_chunk_findings() {
+        let header = ImageHeader::new(1, 1, ColorType::Rgb, 8).unwrap();
+        let chunks = vec![
+            header.to_chunk(),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![0; 4]),
+            Chunk::new(ChunkType::from_str("RUSt").unwrap(), vec![]),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), vec![0; 32]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        let png = Png::from_chunks(chunks);
+
+        let findings = chunk_findings(&png, 0, 16);
+        let tests: Vec<&str> = findings.iter().map(|f| f.test.as_str()).collect();
+        assert_eq!(
+            tests,
+            vec![
+                "private chunk",
+                "unknown chunk",
+                "oversized ancillary chunk",
+                "trailing data"
+            ]
+        );
+        assert_eq!(findings.last().unwrap().score, 0.0);
+
+        let findings = chunk_findings(&png, 10, 1024);
+        assert_eq!(findings.last().unwrap().score, 1.0);
+    }
+
+    #[test]
+    fn test_lsb_findings_not_applicable() {
+        let header = ImageHeader::new(2, 2, ColorType::Indexed, 8).unwrap();
+        let raw_image = RawImage {
+            header,
+            data: vec![0; 4],
+        };
+        let findings = lsb_findings(&raw_image);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].score, 0.0);
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Args, Subcommand};
+use clap::{Args, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Subcommand)]
@@ -7,28 +7,480 @@ pub enum PngMeArgs {
     Decode(DecodeArgs),
     Remove(RemoveArgs),
     Print(PrintArgs),
+    /// Reads or sets the physical pixel dimensions stored in the pHYs chunk
+    Dpi(DpiArgs),
+    /// Inspects or edits the PLTE, tRNS and bKGD chunks of indexed-color images
+    Palette(PaletteArgs),
+    /// Inspects the compressed image data stored in IDAT chunks
+    Idat(IdatArgs),
+    /// Looks for signs of hidden data in the chunks and pixels of a PNG file
+    Analyze(AnalyzeArgs),
+    /// Creates an X25519 key pair for encode --recipient and decode --identity
+    Keygen(KeygenArgs),
+    /// Signs the image header, image data and chosen ancillary chunks with an Ed25519 key
+    Sign(SignArgs),
+    /// Checks the signature of a PNG file and reports which chunks are covered or altered
+    Verify(VerifyArgs),
+    /// Compares the chunks and decoded pixels of two PNG files
+    Diff(DiffArgs),
+    /// Shows the data of one chunk as a hex dump with an ASCII column
+    Dump(DumpArgs),
+    /// Writes the data of one chunk, or the whole chunk, to a file
+    Extract(ExtractArgs),
+    /// Inserts a chunk built from raw data, or a complete chunk read from a file. A type that
+    /// may appear only once replaces the existing chunk with --force.
+    Inject(InjectArgs),
+    /// Copies ancillary chunks such as text and pHYs from one PNG file into another
+    CopyMetadata(CopyMetadataArgs),
+    /// Removes metadata chunks and reports the bytes saved
+    Strip(StripArgs),
 }
 #[derive(Args, Debug)]
 pub struct EncodeArgs {
+    /// PNG file to read, or - for stdin
     pub filepath: PathBuf,
     pub chunk_type: String,
-    pub message: String,
-    pub output_file: Option<String>,
+    /// Text to store, or - to read it from stdin. Leave out when using --file
+    #[arg(required_unless_present = "file", conflicts_with = "file")]
+    pub message: Option<String>,
+    /// Same as --output
+    #[arg(conflicts_with_all = ["output", "in_place"])]
+    pub output_file: Option<PathBuf>,
+    /// Stores the raw bytes of this file, with its name and MIME type, instead of a message
+    #[arg(long, value_name = "PAYLOAD_FILE")]
+    pub file: Option<PathBuf>,
+    #[command(flatten)]
+    pub method: MethodArgs,
+    /// Encrypts the message with a key derived from --passphrase
+    #[arg(long, requires = "passphrase")]
+    pub encrypt: bool,
+    /// Encrypts the message for the X25519 public key in this file (repeatable)
+    #[arg(
+        long = "recipient",
+        value_name = "PUBKEY_FILE",
+        conflicts_with = "encrypt"
+    )]
+    pub recipients: Vec<PathBuf>,
+    /// Compresses the message before storing it, with deflate unless --compress=zstd is given
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "deflate"
+    )]
+    pub compress: Option<CompressionFormat>,
+    /// Splits stored payloads larger than this many bytes into numbered chunks (chunk only)
+    #[arg(long, default_value_t = 1 << 20)]
+    pub max_chunk_size: u32,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionFormat {
+    Deflate,
+    Zstd,
 }
 
 #[derive(Args, Debug)]
 pub struct DecodeArgs {
+    /// PNG file to read, or - for stdin
     pub filepath: PathBuf,
     pub chunk_type: String,
+    #[command(flatten)]
+    pub method: MethodArgs,
+    /// Decrypts a message stored with --encrypt using --passphrase
+    #[arg(long, requires = "passphrase")]
+    pub decrypt: bool,
+    /// Decrypts a message stored with --recipient using the secret key in this file
+    #[arg(long, value_name = "KEY_FILE", conflicts_with = "decrypt")]
+    pub identity: Option<PathBuf>,
+    /// Writes the raw message bytes to this file, or - for stdout, instead of printing them
+    #[arg(long, value_name = "OUTPUT_FILE")]
+    pub output: Option<PathBuf>,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbedMethod {
+    /// Store the message in its own chunk
+    Chunk,
+    /// Hide the message in the least-significant bits of the pixels
+    Lsb,
+}
+
+#[derive(Args, Debug)]
+pub struct MethodArgs {
+    #[arg(long, value_enum, default_value = "chunk")]
+    pub method: EmbedMethod,
+    /// Message bits stored in each selected channel, from 1 to 8 (lsb only)
+    #[arg(long, default_value_t = 1)]
+    pub bits: u8,
+    /// Channels to embed in, such as rgb, rgba, y or ya (lsb only, defaults to all but alpha)
+    #[arg(long)]
+    pub channels: Option<String>,
+    /// Passphrase for --encrypt and --decrypt. With lsb, also scatters the message over pixel
+    /// positions chosen by this passphrase
+    #[arg(long, env = "PNGME_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+    /// Shared key for an HMAC-SHA256 tag over the chunk type and message. Tags are added on
+    /// encode and checked on decode
+    #[arg(long, env = "PNGME_HMAC_KEY", hide_env_values = true)]
+    pub hmac_key: Option<String>,
 }
 
 #[derive(Args, Debug)]
 pub struct RemoveArgs {
     pub filepath: PathBuf,
     pub chunk_type: String,
+    #[command(flatten)]
+    pub output: OutputArgs,
 }
 
 #[derive(Args, Debug)]
 pub struct PrintArgs {
     pub filepath: PathBuf,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct DpiArgs {
+    #[command(subcommand)]
+    pub command: DpiCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DpiCommand {
+    Get(DpiGetArgs),
+    Set(DpiSetArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DpiGetArgs {
+    pub filepath: PathBuf,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct DpiSetArgs {
+    pub filepath: PathBuf,
+    pub dpi: f64,
+    /// Vertical DPI, defaults to the horizontal value
+    #[arg(long)]
+    pub dpi_y: Option<f64>,
+    /// Same as --output
+    #[arg(conflicts_with_all = ["output", "in_place"])]
+    pub output_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PaletteArgs {
+    #[command(subcommand)]
+    pub command: PaletteCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PaletteCommand {
+    Dump(PaletteDumpArgs),
+    SetEntry(PaletteSetEntryArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PaletteFormat {
+    Json,
+    Gpl,
+}
+
+#[derive(Args, Debug)]
+pub struct PaletteDumpArgs {
+    pub filepath: PathBuf,
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: PaletteFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct PaletteSetEntryArgs {
+    pub filepath: PathBuf,
+    pub index: usize,
+    /// New color written as #rrggbb
+    pub color: String,
+    /// Alpha value stored in the tRNS chunk for this entry
+    #[arg(long)]
+    pub alpha: Option<u8>,
+    /// Same as --output
+    #[arg(conflicts_with_all = ["output", "in_place"])]
+    pub output_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct IdatArgs {
+    #[command(subcommand)]
+    pub command: IdatCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdatCommand {
+    Info(IdatInfoArgs),
+    /// Decodes the pixels and writes them as a raw RGBA8 or RGBA16 buffer
+    Decode(IdatDecodeArgs),
+    /// Encodes a raw pixel buffer into a new PNG file
+    Encode(IdatEncodeArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct IdatInfoArgs {
+    pub filepath: PathBuf,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct IdatDecodeArgs {
+    pub filepath: PathBuf,
+    pub output_file: PathBuf,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PixelFormat {
+    Grayscale,
+    GrayscaleAlpha,
+    Rgb,
+    Rgba,
+    Indexed,
+}
+
+#[derive(Args, Debug)]
+pub struct IdatEncodeArgs {
+    /// Unfiltered pixel rows, with 16-bit samples in big-endian order
+    pub pixels_file: PathBuf,
+    pub output_file: PathBuf,
+    #[arg(long)]
+    pub width: u32,
+    #[arg(long)]
+    pub height: u32,
+    #[arg(long, value_enum, default_value = "rgba")]
+    pub color_type: PixelFormat,
+    #[arg(long, default_value_t = 8)]
+    pub bit_depth: u8,
+    /// Maximum number of compressed bytes per IDAT chunk
+    #[arg(long, default_value_t = 8192)]
+    pub idat_size: usize,
+    /// zlib compression level from 0 to 9
+    #[arg(long, default_value_t = 6)]
+    pub level: u32,
+    /// Comma-separated #rrggbb palette entries for indexed images
+    #[arg(long, value_delimiter = ',')]
+    pub palette: Vec<String>,
+    /// Comma-separated alpha values for the leading palette entries
+    #[arg(long, value_delimiter = ',')]
+    pub alpha: Vec<u8>,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    pub filepath: PathBuf,
+    /// Flags ancillary chunks with more data bytes than this
+    #[arg(long, default_value_t = 65536)]
+    pub max_ancillary_size: u32,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// Where to write the secret key. The public key is written alongside it with a .pub suffix
+    pub output_file: PathBuf,
+    /// Creates an Ed25519 key pair for sign and verify instead
+    #[arg(long)]
+    pub signing: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SignArgs {
+    pub filepath: PathBuf,
+    /// File holding the Ed25519 signing key
+    #[arg(long, value_name = "KEY_FILE")]
+    pub key: PathBuf,
+    /// Ancillary chunk types to cover in addition to IHDR, PLTE and IDAT, such as tEXt,pHYs
+    #[arg(long, value_delimiter = ',')]
+    pub include: Vec<String>,
+    /// Same as --output
+    #[arg(conflicts_with_all = ["output", "in_place"])]
+    pub output_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    pub filepath: PathBuf,
+    /// File holding the Ed25519 verifying key
+    #[arg(long, value_name = "PUBKEY_FILE")]
+    pub pubkey: PathBuf,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    pub old_file: PathBuf,
+    pub new_file: PathBuf,
+    /// Prints plain text, or a JSON document with a stable schema
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: ReportFormat,
+}
+
+/// Picks one chunk out of a PNG file
+#[derive(Args, Debug)]
+pub struct ChunkSelector {
+    #[arg(long = "type", value_name = "CHUNK_TYPE")]
+    pub chunk_type: String,
+    /// Which chunk of that type to use, counting from 0 in file order
+    #[arg(long, default_value_t = 0)]
+    pub index: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct DumpArgs {
+    /// PNG file to read, or - for stdin
+    pub filepath: PathBuf,
+    #[command(flatten)]
+    pub chunk: ChunkSelector,
+}
+
+#[derive(Args, Debug)]
+pub struct ExtractArgs {
+    /// PNG file to read, or - for stdin
+    pub filepath: PathBuf,
+    #[command(flatten)]
+    pub chunk: ChunkSelector,
+    /// Where to write the chunk, or - for stdout
+    pub output_file: PathBuf,
+    /// Writes the length, type and CRC along with the data
+    #[arg(long)]
+    pub full: bool,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct InjectArgs {
+    /// PNG file to read, or - for stdin
+    pub filepath: PathBuf,
+    /// Type of the chunk built from --data-file
+    #[arg(long = "type", value_name = "CHUNK_TYPE", requires = "data_file")]
+    pub chunk_type: Option<String>,
+    /// File holding the data of the new chunk
+    #[arg(
+        long,
+        value_name = "DATA_FILE",
+        requires = "chunk_type",
+        required_unless_present = "chunk_file",
+        conflicts_with = "chunk_file"
+    )]
+    pub data_file: Option<PathBuf>,
+    /// File holding a complete chunk with length, type, data and CRC, as written by
+    /// extract --full
+    #[arg(long, value_name = "CHUNK_FILE")]
+    pub chunk_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CopyMetadataArgs {
+    /// PNG file to copy chunks from, or - for stdin
+    pub source_file: PathBuf,
+    /// PNG file to copy chunks into
+    pub filepath: PathBuf,
+    /// Copies exactly these chunk types, even ones not marked safe to copy, such as gAMA,tEXt
+    #[arg(long, value_delimiter = ',')]
+    pub include: Vec<String>,
+    /// Chunk types never to copy
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StripArgs {
+    /// PNG file to read, or - for stdin
+    pub filepath: PathBuf,
+    #[command(flatten)]
+    pub preset: StripPresetArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+/// Which chunks strip removes. Exactly one preset is required.
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct StripPresetArgs {
+    /// Removes every ancillary chunk
+    #[arg(long)]
+    pub all_ancillary: bool,
+    /// Removes every ancillary chunk except gAMA, cHRM, sRGB and iCCP
+    #[arg(long)]
+    pub keep_color: bool,
+    /// Removes text, eXIf, tIME and private chunks
+    #[arg(long)]
+    pub privacy: bool,
+}
+
+/// Options shared by commands that rewrite a PNG file
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    /// Where to write the result, or - for stdout
+    #[arg(short = 'o', long, value_name = "OUTPUT_FILE")]
+    pub output: Option<PathBuf>,
+    /// Overwrites the input file instead of writing to --output
+    #[arg(long, conflicts_with = "output")]
+    pub in_place: bool,
+    #[command(flatten)]
+    pub write: WriteArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct WriteArgs {
+    /// Allows overwriting an existing output file
+    #[arg(long)]
+    pub force: bool,
+    /// Prints what would be written, including the chunks a rewrite would add, remove or
+    /// replace, without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Keeps the previous file with this suffix, .bak unless --backup=SUFFIX is given
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".bak"
+    )]
+    pub backup: Option<String>,
 }
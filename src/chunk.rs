@@ -4,7 +4,7 @@ use crc::{CRC_32_ISO_HDLC, Crc};
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chunk {
     pub data_length: u32,
     pub chunk_type: ChunkType,
@@ -16,6 +16,8 @@ pub struct Chunk {
 pub enum ChunkError {
     InvalidCRC(String),
     NotFound(String),
+    InvalidLength(String),
+    InvalidType(String),
 }
 
 impl fmt::Display for ChunkError {
@@ -23,6 +25,8 @@ impl fmt::Display for ChunkError {
         match self {
             ChunkError::InvalidCRC(e) => write!(f, "Chunk Error: {}", e),
             ChunkError::NotFound(e) => write!(f, "Chunk Error: {}", e),
+            ChunkError::InvalidLength(e) => write!(f, "Chunk Error: {}", e),
+            ChunkError::InvalidType(e) => write!(f, "Chunk Error: {}", e),
         }
     }
 }
@@ -91,31 +95,44 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(chunk_data: &[u8]) -> Result<Self> {
+        if chunk_data.len() < 12 {
+            let error_message = format!("{} bytes is too short for a chunk", chunk_data.len());
+            return Err(ChunkError::InvalidLength(error_message).into());
+        }
         let length: [u8; 4] = chunk_data[0..=3].try_into()?;
         let length: u32 = u32::from_be_bytes(length);
+        if chunk_data.len() as u64 != length as u64 + 12 {
+            let error_message = format!(
+                "Chunk declares {} data bytes but {} bytes were given",
+                length,
+                chunk_data.len()
+            );
+            return Err(ChunkError::InvalidLength(error_message).into());
+        }
 
         let chunk_type: [u8; 4] = chunk_data[4..=7].try_into()?;
         let chunk_type = ChunkType::try_from(chunk_type)?;
 
         let data_range: usize = (length + 7) as usize;
-        let data: Vec<u8> = chunk_data[8..=data_range].try_into()?;
+        let data: Vec<u8> = chunk_data[8..=data_range].into();
 
         let crc: [u8; 4] = chunk_data[(data_range + 1)..].try_into()?;
         let crc = u32::from_be_bytes(crc);
 
         let validated_crc = get_crc(&chunk_type, &data);
-        let is_valid_crc = validated_crc == crc;
+        if validated_crc != crc {
+            let error_message = format!(
+                "Invalid CRC for {} chunk: stored {:08x}, computed {:08x}",
+                chunk_type, crc, validated_crc
+            );
+            return Err(ChunkError::InvalidCRC(error_message).into());
+        }
 
-        let chunk = Chunk {
+        Ok(Chunk {
             data_length: length,
             chunk_type,
             chunk_data: data,
             crc,
-        };
-
-        is_valid_crc.then_some(chunk).ok_or({
-            let error_message = "Invalid CRC".to_string();
-            ChunkError::InvalidCRC(error_message).into()
         })
     }
 }
@@ -248,6 +265,18 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_from_bytes_wrong_length() {
+        let chunk = testing_chunk().as_bytes();
+
+        assert!(Chunk::try_from(&chunk[..11]).is_err());
+        assert!(Chunk::try_from(&chunk[..chunk.len() - 1]).is_err());
+
+        let mut extended = chunk.clone();
+        extended.push(0);
+        assert!(Chunk::try_from(extended.as_ref()).is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -4,7 +4,7 @@ use std::str::FromStr;
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
     pub chunk_type: [u8; 4],
 }
@@ -23,39 +23,51 @@ impl fmt::Display for ChunkTypeError {
     }
 }
 
+/// Chunk types defined by the PNG specification and its registered extensions
+pub const KNOWN_CHUNK_TYPES: [&str; 34] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI",
+    "bKGD", "hIST", "tRNS", "eXIf", "pHYs", "sPLT", "tIME", "iTXt", "tEXt", "zTXt", "acTL", "fcTL",
+    "fdAT", "oFFs", "pCAL", "sCAL", "sTER", "gIFg", "gIFx", "gIFt", "fRAc", "dSIG",
+];
+
 impl ChunkType {
-    fn bytes(&self) -> [u8; 4] {
+    pub fn bytes(&self) -> [u8; 4] {
         self.chunk_type
     }
 
-    fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         let first_byte = self.chunk_type.first().unwrap();
         let char = char::from(*first_byte);
 
         char == char.to_ascii_uppercase()
     }
 
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         let second_byte = self.chunk_type.get(1).unwrap();
         let char = char::from(*second_byte);
 
         char == char.to_ascii_uppercase()
     }
 
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         let third_byte = self.chunk_type.get(2).unwrap();
         let char = char::from(*third_byte);
 
         char == char.to_ascii_uppercase()
     }
 
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         let fourth_byte = self.chunk_type.get(3).unwrap();
         let char = char::from(*fourth_byte);
 
         char == char.to_ascii_lowercase()
     }
 
+    /// Returns true if the chunk type is defined by the PNG specification
+    pub fn is_known(&self) -> bool {
+        KNOWN_CHUNK_TYPES.contains(&self.to_string().as_str())
+    }
+
     fn is_valid(&self) -> bool {
         let valid_ascii_alphabet = self.chunk_type.iter().all(|byte| byte.is_ascii_alphabetic());
         valid_ascii_alphabet && self.is_reserved_bit_valid()
@@ -198,6 +210,14 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_known() {
+        assert!(ChunkType::from_str("IHDR").unwrap().is_known());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_known());
+        assert!(ChunkType::from_str("oFFs").unwrap().is_known());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_known());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,51 +1,247 @@
 use std::convert::TryFrom;
 use std::fs;
-use std::fs::File;
-use std::io::Read;
-use std::fs::read;
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::env;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
+use crate::analyze::{self, Finding};
+use crate::args::{
+    AnalyzeArgs, ChunkSelector, CompressionFormat, CopyMetadataArgs, DecodeArgs, DiffArgs,
+    DpiGetArgs, DpiSetArgs, DumpArgs, EmbedMethod, EncodeArgs, ExtractArgs, IdatDecodeArgs,
+    IdatEncodeArgs, IdatInfoArgs, InjectArgs, KeygenArgs, MethodArgs, OutputArgs, PaletteDumpArgs,
+    PaletteFormat, PaletteSetEntryArgs, PixelFormat, PrintArgs, RemoveArgs, ReportFormat, SignArgs,
+    StripArgs, VerifyArgs, WriteArgs,
+};
+use crate::crypto::{self, CryptoError};
+use crate::diff;
+use crate::encoder::PngEncoder;
+use crate::hexdump;
+use crate::ihdr::{ColorType, ImageHeader};
+use crate::lsb::{self, LsbOptions};
+use crate::metadata::{self, StripPreset};
+use crate::output::{self, OutputError, STDIO_PATH, is_stdio};
+use crate::palette::{Background, Palette, PaletteError, Rgb, Transparency};
+use crate::payload::{self, Algorithm, FileInfo, Layers, PayloadError};
+use crate::phys::PhysicalDimensions;
 use crate::png::{Chunk, ChunkType, Png};
+use crate::report;
+use crate::signature::{self, Manifest, SignatureError, Status};
 use crate::{Result, chunk};
+use x25519_dalek::PublicKey;
 
 /// Encodes a message into a PNG file and saves the result
 pub fn encode(args: EncodeArgs) -> Result<()> {
-    let png_file = read(&args.filepath)?;
+    let message_from_stdin = match (&args.file, &args.message) {
+        (Some(path), _) => is_stdio(path),
+        (None, message) => message.as_deref() == Some(STDIO_PATH),
+    };
+    if is_stdio(&args.filepath) && message_from_stdin {
+        let error_message = "Only one of the PNG file and the message can come from stdin";
+        return Err(PayloadError::InvalidSource(error_message.to_string()).into());
+    }
+    if args.method.method == EmbedMethod::Chunk && args.method.passphrase.is_some() && !args.encrypt
+    {
+        let error_message = "--passphrase has no effect on a chunk without --encrypt";
+        return Err(CryptoError::KeyDerivation(error_message.to_string()).into());
+    }
+
+    let png_file = read_file(&args.filepath)?;
     let mut result = Png::try_from(&png_file[..])?;
 
     let chunk_type = ChunkType::from_str(&args.chunk_type)?;
-    let chunk = Chunk::new(chunk_type, args.message.as_bytes().into());
+    let mut layers = Layers {
+        file: args.file.is_some(),
+        compressed: args.compress.is_some(),
+        ..Layers::default()
+    };
 
-    result.append_chunk(chunk);
+    let message = match (&args.file, &args.message) {
+        (Some(path), _) => payload::wrap_file(&FileInfo::for_path(path)?, &read_file(path)?)?,
+        (None, Some(message)) if message == STDIO_PATH => read_file(&PathBuf::from(message))?,
+        (None, Some(message)) => message.as_bytes().to_vec(),
+        (None, None) => unreachable!("clap requires a message or --file"),
+    };
 
-    let current_dir = env::current_dir()?;
-    
-    match args.output_file {
-        Some(output_path) => {
-            let file_path = PathBuf::from(current_dir).join(output_path);
-            fs::write(file_path, result.as_bytes())?;
-        },
-        None => fs::write(args.filepath, result.as_bytes())?
+    let payload = match args.compress {
+        Some(format) => {
+            let algorithm = match format {
+                CompressionFormat::Deflate => Algorithm::Deflate,
+                CompressionFormat::Zstd => Algorithm::Zstd,
+            };
+            let compressed = payload::compress(algorithm, &message)?;
+            eprintln!(
+                "Compressed {} bytes to {} bytes with {}",
+                message.len(),
+                compressed.len(),
+                algorithm
+            );
+            compressed
+        }
+        None => message,
+    };
+
+    let payload = match (&args.method.passphrase, args.encrypt) {
+        (Some(passphrase), true) => {
+            layers.encrypted = true;
+            crypto::seal_with_passphrase(passphrase, &payload)?
+        }
+        _ if !args.recipients.is_empty() => {
+            layers.encrypted = true;
+            let recipients = args
+                .recipients
+                .iter()
+                .map(|path| crypto::parse_public_key(&fs::read_to_string(path)?))
+                .collect::<Result<Vec<_>>>()?;
+            crypto::seal_for_recipients(&recipients, &payload)?
+        }
+        _ => payload,
+    };
+    let payload = match &args.method.hmac_key {
+        Some(key) => {
+            layers.tagged = true;
+            crypto::attach_tag(key.as_bytes(), &chunk_type, layers.flags(), &payload)
+        }
+        None => payload,
+    };
+
+    match args.method.method {
+        EmbedMethod::Chunk => {
+            if args.max_chunk_size > i32::MAX as u32 {
+                let error_message = "Chunks hold at most 2^31 - 1 bytes".to_string();
+                return Err(PayloadError::InvalidHeader(error_message).into());
+            }
+            let pieces = payload::frame_pieces(layers, &payload, args.max_chunk_size as usize)?;
+            if pieces.len() > 1 {
+                eprintln!("Split {} bytes into {} chunks", payload.len(), pieces.len());
+            }
+            for piece in pieces {
+                result.append_chunk(Chunk::new(chunk_type.clone(), piece));
+            }
+        }
+        EmbedMethod::Lsb => {
+            let framed = payload::frame(layers, &payload);
+            embed_lsb(&mut result, &args.method, &chunk_type, &framed)?
+        }
     }
 
+    save(
+        &png_file,
+        &result,
+        &args.filepath,
+        args.output_file,
+        &args.output,
+    )?;
+
     Ok(())
 }
 
 /// Searches for a message hidden in a PNG file and prints the message if one is found
 pub fn decode(args: DecodeArgs) -> Result<()> {
+    if args.format == ReportFormat::Json && args.output.as_deref().is_some_and(is_stdio) {
+        let error_message =
+            "--format json cannot be used with --output -, which writes the message to stdout";
+        return Err(OutputError::InvalidPath(error_message.to_string()).into());
+    }
+
     let png_file = read_file(&args.filepath)?;
     let result = Png::try_from(&png_file[..])?;
-    let decoded_message =
-        result
-            .chunk_by_type(args.chunk_type.as_str())
-            .ok_or(chunk::ChunkError::NotFound(
-                "Chunktype not found.".to_string(),
-            ))?;
 
-    println!("{}", decoded_message.data_as_string()?);
+    let (layers, payload) = read_payload(&result, &args)?;
+    let payload = match (&args.method.hmac_key, layers.tagged) {
+        (Some(key), true) => {
+            let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+            crypto::check_tag(key.as_bytes(), &chunk_type, layers.flags(), &payload)?
+        }
+        (Some(_), false) => {
+            let error_message = "Message has no HMAC tag".to_string();
+            return Err(CryptoError::Authentication(error_message).into());
+        }
+        (None, true) => {
+            let error_message = "Message carries an HMAC tag. Pass --hmac-key.".to_string();
+            return Err(CryptoError::Authentication(error_message).into());
+        }
+        (None, false) => payload,
+    };
+
+    let wants_decryption = args.decrypt || args.identity.is_some();
+    let message = match (&args.method.passphrase, args.decrypt, &args.identity) {
+        _ if layers.encrypted != wants_decryption => {
+            let error_message = if layers.encrypted {
+                "Message is encrypted. Pass --decrypt with --passphrase, or --identity."
+            } else {
+                "Message is not encrypted"
+            };
+            return Err(CryptoError::InvalidEnvelope(error_message.to_string()).into());
+        }
+        (Some(passphrase), true, _) => crypto::open_with_passphrase(passphrase, &payload)?,
+        (_, _, Some(identity)) => {
+            let identity = crypto::parse_identity(&fs::read_to_string(identity)?)?;
+            crypto::open_with_identity(&identity, &payload)?
+        }
+        _ => payload,
+    };
+
+    let message = if layers.compressed {
+        payload::decompress(&message)?.1
+    } else {
+        message
+    };
+
+    let (info, message) = if layers.file {
+        let (info, contents) = payload::unwrap_file(&message)?;
+        (Some(info), contents)
+    } else {
+        (None, message)
+    };
+
+    match (&args.output, &info) {
+        (Some(output_path), _) => {
+            write_file(output_path, &message, None)?;
+            let description = info
+                .as_ref()
+                .map(|info| format!(" of {}", info))
+                .unwrap_or_default();
+            eprintln!(
+                "Wrote {} bytes{} to {}",
+                message.len(),
+                description,
+                output_path.display()
+            );
+            if args.format == ReportFormat::Json {
+                let document = report::decoded(
+                    &args.chunk_type,
+                    message.len(),
+                    None,
+                    info.as_ref(),
+                    Some(output_path),
+                );
+                print_json(&document)?;
+            }
+        }
+        (None, Some(info)) if std::str::from_utf8(&message).is_err() => {
+            let error_message = format!("Message is the binary file {}. Pass --output.", info);
+            return Err(PayloadError::Binary(error_message).into());
+        }
+        (None, _) => {
+            let message = String::from_utf8(message)?;
+            match args.format {
+                ReportFormat::Text => println!("{}", message),
+                ReportFormat::Json => {
+                    let document = report::decoded(
+                        &args.chunk_type,
+                        message.len(),
+                        Some(&message),
+                        info.as_ref(),
+                        None,
+                    );
+                    print_json(&document)?;
+                }
+            }
+        }
+    }
 
     Ok(())
 }
@@ -56,13 +252,22 @@ pub fn remove(args: RemoveArgs) -> Result<()> {
     let mut png = Png::try_from(&png_file[..])?;
     let result = png.remove_first_chunk(&args.chunk_type)?;
 
-    fs::write(&args.filepath, png.as_bytes())?;
+    save(&png_file, &png, &args.filepath, None, &args.output)?;
+    if args.output.write.dry_run {
+        return Ok(());
+    }
 
-    println!(
-        "\nRemoved chunk: {} \nMessage: {}",
-        result.chunk_type,
-        result.data_as_string()?
-    );
+    match result.data_as_string() {
+        Ok(message) => eprintln!(
+            "\nRemoved chunk: {} \nMessage: {}",
+            result.chunk_type, message
+        ),
+        Err(_) => eprintln!(
+            "\nRemoved chunk: {} \nData: {} bytes",
+            result.chunk_type,
+            result.length()
+        ),
+    }
 
     Ok(())
 }
@@ -72,6 +277,11 @@ pub fn print_chunks(args: PrintArgs) -> Result<()> {
     let png_file = read_file(&args.filepath)?;
     let result = Png::try_from(&png_file[..])?;
 
+    if args.format == ReportFormat::Json {
+        let trailing_len = png_file.len().saturating_sub(result.as_bytes().len());
+        return print_json(&report::chunks(&result, trailing_len));
+    }
+
     for chunk in result.chunks() {
         println!("{}", chunk)
     }
@@ -79,10 +289,725 @@ pub fn print_chunks(args: PrintArgs) -> Result<()> {
     Ok(())
 }
 
+/// Prints the physical pixel dimensions stored in a PNG file
+pub fn dpi_get(args: DpiGetArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+
+    let phys = png
+        .chunk_by_type(PhysicalDimensions::CHUNK_TYPE)
+        .map(PhysicalDimensions::try_from)
+        .transpose()?;
+
+    match (args.format, phys) {
+        (ReportFormat::Json, phys) => print_json(&report::phys(phys.as_ref()))?,
+        (ReportFormat::Text, Some(phys)) => println!("{}", phys),
+        (ReportFormat::Text, None) => println!("No pHYs chunk found."),
+    }
+
+    Ok(())
+}
+
+/// Sets the DPI of a PNG file by inserting or replacing its pHYs chunk
+pub fn dpi_set(args: DpiSetArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let mut png = Png::try_from(&png_file[..])?;
+
+    let phys = PhysicalDimensions::from_dpi(args.dpi, args.dpi_y.unwrap_or(args.dpi))?;
+    png.insert_chunk(phys.to_chunk(), true);
+
+    save(
+        &png_file,
+        &png,
+        &args.filepath,
+        args.output_file,
+        &args.output,
+    )?;
+    if args.output.write.dry_run {
+        return Ok(());
+    }
+
+    eprintln!("{}", phys);
+
+    Ok(())
+}
+
+/// Prints the palette of a PNG file as JSON or as a GIMP palette
+pub fn palette_dump(args: PaletteDumpArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+    let (header, palette, transparency) = read_palette(&png)?;
+
+    let background = png
+        .chunk_by_type(Background::CHUNK_TYPE)
+        .map(|chunk| Background::from_chunk(chunk, &header, Some(&palette)))
+        .transpose()?;
+
+    match args.format {
+        PaletteFormat::Json => println!(
+            "{}",
+            palette.to_json(transparency.as_ref(), background.as_ref())?
+        ),
+        PaletteFormat::Gpl => {
+            let name = args
+                .filepath
+                .file_stem()
+                .map_or("pngme".into(), |stem| stem.to_string_lossy());
+            print!("{}", palette.to_gpl(&name, transparency.as_ref()))
+        }
+    }
+
+    Ok(())
+}
+
+/// Recolors a single palette entry without touching the image data
+pub fn palette_set_entry(args: PaletteSetEntryArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let mut png = Png::try_from(&png_file[..])?;
+    let (header, mut palette, transparency) = read_palette(&png)?;
+
+    let color = Rgb::from_str(&args.color)?;
+    palette.set_entry(args.index, color)?;
+    png.insert_chunk(palette.to_chunk(), true);
+
+    if let Some(alpha) = args.alpha {
+        let mut transparency = transparency.unwrap_or(Transparency::Indexed(vec![]));
+        transparency.set_alpha(args.index, alpha)?;
+        transparency.validate(&header, Some(&palette))?;
+        png.insert_chunk(transparency.to_chunk(), true);
+    }
+
+    save(
+        &png_file,
+        &png,
+        &args.filepath,
+        args.output_file,
+        &args.output,
+    )?;
+    if args.output.write.dry_run {
+        return Ok(());
+    }
+
+    eprintln!("Set palette entry {} to {}", args.index, color);
+
+    Ok(())
+}
+
+/// Prints the compressed and raw sizes of the image data in a PNG file
+pub fn idat_info(args: IdatInfoArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+    let image_data = png.image_data()?;
+
+    let mut filter_counts = [0usize; 5];
+    for scanline in image_data.scanlines() {
+        if let Some(count) = filter_counts.get_mut(scanline.filter_type as usize) {
+            *count += 1;
+        }
+    }
+
+    if args.format == ReportFormat::Json {
+        return print_json(&report::image_data(&image_data, &filter_counts));
+    }
+
+    println!("Image: {}", image_data.header);
+    println!("IDAT chunks: {}", image_data.idat_count);
+    println!("Compressed: {} bytes", image_data.compressed_len);
+    println!("Raw: {} bytes", image_data.raw.len());
+    println!(
+        "Ratio: {:.2}%",
+        image_data.compressed_len as f64 / image_data.raw.len() as f64 * 100.0
+    );
+    println!("Stride: {} bytes", image_data.stride());
+    println!("Passes: {}", image_data.passes().len());
+    println!(
+        "Filters: None {}, Sub {}, Up {}, Average {}, Paeth {}",
+        filter_counts[0], filter_counts[1], filter_counts[2], filter_counts[3], filter_counts[4]
+    );
+
+    Ok(())
+}
+
+/// Decodes the pixels of a PNG file and writes them as a raw RGBA buffer
+pub fn idat_decode(args: IdatDecodeArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+    let decoded = png.decode()?;
+
+    if !write_output(&args.output_file, &decoded.as_bytes(), &args.write)? {
+        return Ok(());
+    }
+    eprintln!(
+        "Wrote {}x{} {} pixels to {}",
+        decoded.width,
+        decoded.height,
+        decoded.pixels,
+        args.output_file.display()
+    );
+
+    Ok(())
+}
+
+/// Encodes a raw pixel buffer into a new PNG file
+pub fn idat_encode(args: IdatEncodeArgs) -> Result<()> {
+    let pixels = read_file(&args.pixels_file)?;
+    let color_type = match args.color_type {
+        PixelFormat::Grayscale => ColorType::Grayscale,
+        PixelFormat::GrayscaleAlpha => ColorType::GrayscaleAlpha,
+        PixelFormat::Rgb => ColorType::Rgb,
+        PixelFormat::Rgba => ColorType::Rgba,
+        PixelFormat::Indexed => ColorType::Indexed,
+    };
+
+    let mut encoder = PngEncoder::new(args.width, args.height, color_type, args.bit_depth)?
+        .with_idat_chunk_size(args.idat_size)?
+        .with_compression_level(args.level)?;
+
+    if !args.palette.is_empty() {
+        let entries = args
+            .palette
+            .iter()
+            .map(|color| Rgb::from_str(color))
+            .collect::<Result<Vec<Rgb>>>()?;
+        encoder = encoder.with_palette(Palette { entries })?;
+    }
+    if !args.alpha.is_empty() {
+        encoder = encoder.with_transparency(Transparency::Indexed(args.alpha))?;
+    }
+
+    let png = encoder.encode(&pixels)?;
+
+    if !write_output(&args.output_file, &png.as_bytes(), &args.write)? {
+        return Ok(());
+    }
+    eprintln!("Wrote {} to {}", png.header()?, args.output_file.display());
+
+    Ok(())
+}
+
+/// Runs structural and statistical steganalysis tests and prints a suspicion score for each
+pub fn analyze(args: AnalyzeArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+    let trailing_len = png_file.len().saturating_sub(png.as_bytes().len());
+
+    let mut findings = analyze::chunk_findings(&png, trailing_len, args.max_ancillary_size);
+    match png.raw_image() {
+        Ok(raw_image) => findings.extend(analyze::lsb_findings(&raw_image)),
+        Err(e) => {
+            let details = format!("not applicable, the pixel data does not decode: {}", e);
+            findings.push(Finding::new("LSB analysis", 0.0, details));
+        }
+    }
+
+    let overall = findings
+        .iter()
+        .map(|finding: &Finding| finding.score)
+        .fold(0.0, f64::max);
+
+    if args.format == ReportFormat::Json {
+        return print_json(&report::findings(&findings, overall));
+    }
+
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    println!("Overall suspicion: {:.2}", overall);
+
+    Ok(())
+}
+
+/// Writes a new X25519 or Ed25519 secret key and its public key, refusing to overwrite
+/// existing files
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+    let (secret_key, public_key) = if args.signing {
+        let signing_key = crypto::generate_signing_key();
+        (
+            crypto::encode_signing_key(&signing_key),
+            crypto::encode_verifying_key(&signing_key.verifying_key()),
+        )
+    } else {
+        let identity = crypto::generate_identity();
+        (
+            crypto::encode_identity(&identity),
+            crypto::encode_public_key(&PublicKey::from(&identity)),
+        )
+    };
+
+    let mut public_path = args.output_file.clone().into_os_string();
+    public_path.push(".pub");
+    let public_path = PathBuf::from(public_path);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut secret_file = options.open(&args.output_file)?;
+    writeln!(secret_file, "{}", secret_key)?;
+
+    let mut public_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&public_path)?;
+    writeln!(public_file, "{}", public_key)?;
+
+    println!("Secret key: {}", args.output_file.display());
+    println!("Public key: {}", public_path.display());
+    println!("{}", public_key);
+
+    Ok(())
+}
+
+/// Signs the covered chunks of a PNG file and stores the signature in a siGN chunk
+pub fn sign(args: SignArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let mut png = Png::try_from(&png_file[..])?;
+
+    let key = crypto::parse_signing_key(&fs::read_to_string(&args.key)?)?;
+    let include = parse_chunk_types(&args.include)?;
+
+    let manifest = Manifest::sign(&png, &key, &include)?;
+    png.insert_chunk(manifest.to_chunk()?, true);
+
+    save(
+        &png_file,
+        &png,
+        &args.filepath,
+        args.output_file,
+        &args.output,
+    )?;
+    if args.output.write.dry_run {
+        return Ok(());
+    }
+
+    let covered: Vec<String> = manifest
+        .covered_types
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+    eprintln!(
+        "Signed {} chunks of types {}",
+        manifest.entries.len(),
+        covered.join(", ")
+    );
+
+    Ok(())
+}
+
+/// Verifies the siGN chunk of a PNG file and prints the state of every covered chunk
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+
+    let key = crypto::parse_verifying_key(&fs::read_to_string(&args.pubkey)?)?;
+    let manifest = signature::find_manifest(&png)?;
+    let coverage = manifest.verify(&png, &key)?;
+    let changed = coverage
+        .iter()
+        .filter(|chunk| chunk.status != Status::Intact)
+        .count();
+
+    match args.format {
+        ReportFormat::Text => {
+            for chunk in &coverage {
+                println!("{}", chunk);
+            }
+        }
+        ReportFormat::Json => print_json(&report::coverage(&coverage, changed == 0))?,
+    }
+
+    if changed > 0 {
+        let error_message = format!(
+            "{} of {} covered chunks changed since signing",
+            changed,
+            coverage.len()
+        );
+        return Err(SignatureError::InvalidSignature(error_message).into());
+    }
+    if args.format == ReportFormat::Text {
+        println!(
+            "Signature: valid, all {} covered chunks are intact",
+            coverage.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the chunk changes between two PNG files and whether their pixels are the same
+pub fn diff(args: DiffArgs) -> Result<()> {
+    let old_file = read_file(&args.old_file)?;
+    let old = Png::try_from(&old_file[..])?;
+    let new_file = read_file(&args.new_file)?;
+    let new = Png::try_from(&new_file[..])?;
+
+    let changes = diff::chunk_changes(old.chunks(), new.chunks());
+    let (pixels, pixels_details) = match (old.decode(), new.decode()) {
+        (Ok(old_image), Ok(new_image)) if old_image == new_image => ("identical", String::new()),
+        (Ok(old_image), Ok(new_image))
+            if (old_image.width, old_image.height) != (new_image.width, new_image.height) =>
+        {
+            let details = format!(
+                " ({}x{} vs {}x{})",
+                old_image.width, old_image.height, new_image.width, new_image.height
+            );
+            ("different_sizes", details)
+        }
+        (Ok(_), Ok(_)) => ("different", String::new()),
+        (Err(e), _) | (_, Err(e)) => ("undecodable", format!(" ({})", e)),
+    };
+
+    if args.format == ReportFormat::Json {
+        return print_json(&report::changes(&changes, pixels));
+    }
+
+    if changes.is_empty() {
+        println!("No chunk changes");
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    println!("Pixels: {}{}", pixels.replace('_', " "), pixels_details);
+
+    Ok(())
+}
+
+/// Prints one chunk's data as a hex dump
+pub fn dump(args: DumpArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+    let chunk = select_chunk(&png, &args.chunk)?;
+
+    println!(
+        "{} #{}: {} bytes, CRC {:08x}",
+        chunk.chunk_type(),
+        args.chunk.index,
+        chunk.length(),
+        chunk.crc()
+    );
+    println!("{}", hexdump::hexdump(chunk.data()));
+
+    Ok(())
+}
+
+/// Writes one chunk's data, or the whole chunk with --full, to a file
+pub fn extract(args: ExtractArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let png = Png::try_from(&png_file[..])?;
+    let chunk = select_chunk(&png, &args.chunk)?;
+
+    let bytes = if args.full {
+        chunk.as_bytes()
+    } else {
+        chunk.data().to_vec()
+    };
+    if !write_output(&args.output_file, &bytes, &args.write)? {
+        return Ok(());
+    }
+    eprintln!(
+        "Wrote {} bytes of {} #{} to {}",
+        bytes.len(),
+        chunk.chunk_type(),
+        args.chunk.index,
+        args.output_file.display()
+    );
+
+    Ok(())
+}
+
+/// Inserts a chunk from raw data or a pre-built chunk file and saves the result
+pub fn inject(args: InjectArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let mut png = Png::try_from(&png_file[..])?;
+
+    let chunk = match (&args.chunk_type, &args.data_file, &args.chunk_file) {
+        (Some(chunk_type), Some(data_file), _) => {
+            Chunk::new(ChunkType::from_str(chunk_type)?, read_file(data_file)?)
+        }
+        (_, _, Some(chunk_file)) => Chunk::try_from(&read_file(chunk_file)?[..])?,
+        _ => unreachable!("clap requires --data-file with --type, or --chunk-file"),
+    };
+
+    let chunk_type = chunk.chunk_type().to_string();
+    if ["IHDR", "PLTE", "IDAT", "IEND"].contains(&chunk_type.as_str()) {
+        let error_message = format!(
+            "{} chunks cannot be injected. Use the idat or palette commands instead.",
+            chunk_type
+        );
+        return Err(chunk::ChunkError::InvalidType(error_message).into());
+    }
+
+    let replace = metadata::is_single_instance(chunk.chunk_type());
+    let replacing = replace && png.chunk_by_type(&chunk_type).is_some();
+    if replacing && !args.output.write.force {
+        let error_message = format!(
+            "{} may appear only once and the file already has one. Pass --force to replace it.",
+            chunk_type
+        );
+        return Err(chunk::ChunkError::InvalidType(error_message).into());
+    }
+
+    let length = chunk.length();
+    png.insert_chunk(chunk, replace);
+    save(&png_file, &png, &args.filepath, None, &args.output)?;
+    if !args.output.write.dry_run {
+        let action = if replacing { "Replaced" } else { "Injected" };
+        eprintln!("{} {} chunk of {} bytes", action, chunk_type, length);
+    }
+
+    Ok(())
+}
+
+/// Copies ancillary chunks from one PNG file into another and saves the result
+pub fn copy_metadata(args: CopyMetadataArgs) -> Result<()> {
+    let source_file = read_file(&args.source_file)?;
+    let source = Png::try_from(&source_file[..])?;
+    let png_file = read_file(&args.filepath)?;
+    let mut png = Png::try_from(&png_file[..])?;
+
+    let include = parse_chunk_types(&args.include)?;
+    let exclude = parse_chunk_types(&args.exclude)?;
+    let results = metadata::copy_metadata(&source, &mut png, &include, &exclude);
+
+    save(&png_file, &png, &args.filepath, None, &args.output)?;
+    if !args.output.write.dry_run {
+        for result in &results {
+            eprintln!("{}", result);
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the chunks picked by a strip preset and saves the result
+pub fn strip(args: StripArgs) -> Result<()> {
+    let png_file = read_file(&args.filepath)?;
+    let mut png = Png::try_from(&png_file[..])?;
+
+    let preset = match (args.preset.all_ancillary, args.preset.keep_color) {
+        (true, _) => StripPreset::AllAncillary,
+        (_, true) => StripPreset::KeepColor,
+        _ => StripPreset::Privacy,
+    };
+    let removed = metadata::strip(&mut png, preset);
+
+    save(&png_file, &png, &args.filepath, None, &args.output)?;
+
+    let saved = png_file.len().saturating_sub(png.as_bytes().len());
+    if args.output.write.dry_run {
+        eprintln!("Would save {} bytes", saved);
+        return Ok(());
+    }
+    for chunk in &removed {
+        eprintln!("Removed {} ({} bytes)", chunk.chunk_type(), chunk.length());
+    }
+    eprintln!(
+        "Saved {} bytes ({} -> {})",
+        saved,
+        png_file.len(),
+        png_file.len() - saved
+    );
+
+    Ok(())
+}
+
+/// Hides a message in the pixel data and re-encodes the IDAT chunks losslessly
+fn embed_lsb(
+    png: &mut Png,
+    args: &MethodArgs,
+    chunk_type: &ChunkType,
+    message: &[u8],
+) -> Result<()> {
+    let mut raw_image = png.raw_image()?;
+    let options = LsbOptions::new(&raw_image.header, args.bits, args.channels.as_deref())?;
+    let order = match &args.passphrase {
+        Some(passphrase) => lsb::keyed_order(&raw_image.header, passphrase)?,
+        None => lsb::sequential_order(&raw_image.header),
+    };
+
+    lsb::embed(&mut raw_image, &options, &order, chunk_type, message)?;
+
+    let idat_chunks = PngEncoder::for_raw_image(&raw_image)?.encode_image_data(&raw_image.data)?;
+    png.replace_image_data(raw_image.header, idat_chunks);
+
+    eprintln!(
+        "Embedded {} of {} available bytes in the pixel data",
+        message.len(),
+        lsb::capacity(&raw_image, &options)
+    );
+
+    Ok(())
+}
+
+/// Reads the stored bytes from the chunks or the pixel data, depending on the method, and
+/// returns them with the layers their frame header records
+fn read_payload(png: &Png, args: &DecodeArgs) -> Result<(Layers, Vec<u8>)> {
+    if args.method.method == EmbedMethod::Lsb {
+        let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+        let data = extract_lsb(png, &args.method, &chunk_type)?;
+        let frame = payload::unframe(&data)?;
+        return Ok((frame.layers, frame.body.to_vec()));
+    }
+
+    let decoded_message =
+        png.chunk_by_type(args.chunk_type.as_str())
+            .ok_or(chunk::ChunkError::NotFound(
+                "Chunktype not found.".to_string(),
+            ))?;
+
+    let frame = payload::unframe(decoded_message.data())?;
+    if frame.sequenced {
+        let pieces: Vec<&[u8]> = png
+            .chunks_by_type(args.chunk_type.as_str())
+            .into_iter()
+            .filter_map(|chunk| payload::unframe(chunk.data()).ok())
+            .filter(|other| other.sequenced && other.layers == frame.layers)
+            .map(|other| other.body)
+            .collect();
+        return Ok((frame.layers, payload::reassemble(&pieces)?));
+    }
+
+    Ok((frame.layers, frame.body.to_vec()))
+}
+
+fn extract_lsb(png: &Png, args: &MethodArgs, chunk_type: &ChunkType) -> Result<Vec<u8>> {
+    let raw_image = png.raw_image()?;
+    let options = LsbOptions::new(&raw_image.header, args.bits, args.channels.as_deref())?;
+    let order = match &args.passphrase {
+        Some(passphrase) => lsb::keyed_order(&raw_image.header, passphrase)?,
+        None => lsb::sequential_order(&raw_image.header),
+    };
+
+    lsb::extract(&raw_image, &options, &order, chunk_type)
+}
+
+fn read_palette(png: &Png) -> Result<(ImageHeader, Palette, Option<Transparency>)> {
+    let header = png.header()?;
+    let palette = png
+        .chunk_by_type(Palette::CHUNK_TYPE)
+        .ok_or(PaletteError::InvalidChunk(
+            "PLTE chunk not found.".to_string(),
+        ))?;
+    let palette = Palette::from_chunk(palette, &header)?;
+
+    let transparency = png
+        .chunk_by_type(Transparency::CHUNK_TYPE)
+        .map(|chunk| Transparency::from_chunk(chunk, &header, Some(&palette)))
+        .transpose()?;
+
+    Ok((header, palette, transparency))
+}
+
+/// Finds the chunk picked by --type and --index
+fn select_chunk<'a>(png: &'a Png, selector: &ChunkSelector) -> Result<&'a Chunk> {
+    let chunk_type = ChunkType::from_str(&selector.chunk_type)?;
+    let chunks = png.chunks_by_type(&chunk_type.to_string());
+
+    chunks.get(selector.index).copied().ok_or_else(|| {
+        let error_message = format!(
+            "No {} chunk at index {}, the file has {}",
+            chunk_type,
+            selector.index,
+            chunks.len()
+        );
+        chunk::ChunkError::NotFound(error_message).into()
+    })
+}
+
+fn parse_chunk_types(chunk_types: &[String]) -> Result<Vec<ChunkType>> {
+    chunk_types
+        .iter()
+        .map(|chunk_type| ChunkType::from_str(chunk_type))
+        .collect()
+}
+
+/// Prints a report document as indented JSON
+fn print_json(document: &serde_json::Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(document)?);
+    Ok(())
+}
+
+/// Reads a file, or stdin when the path is `-`
 fn read_file(filepath: &PathBuf) -> Result<Vec<u8>> {
-    let mut f = File::open(filepath)?;
     let mut data = vec![];
+    if is_stdio(filepath) {
+        io::stdin().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+
+    let mut f = File::open(filepath)?;
     f.read_to_end(&mut data)?;
 
     Ok(data)
 }
+
+/// Writes a file atomically, optionally keeping a backup, or writes stdout when the path is `-`
+fn write_file(filepath: &Path, data: &[u8], backup_suffix: Option<&str>) -> Result<()> {
+    if is_stdio(filepath) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    output::write_atomic(filepath, data, backup_suffix)
+}
+
+/// Writes a file that is not a rewrite of the input, such as decoded pixels. An existing file
+/// is only replaced with --force. Returns false on a dry run, which only reports the size.
+fn write_output(output_file: &Path, data: &[u8], args: &WriteArgs) -> Result<bool> {
+    let output_path = output::destination(
+        output_file,
+        Some(output_file.to_path_buf()),
+        false,
+        args.force,
+    )?;
+    if args.dry_run {
+        eprintln!(
+            "Dry run: would write {} bytes to {}",
+            data.len(),
+            output_path.display()
+        );
+        return Ok(false);
+    }
+
+    write_file(&output_path, data, args.backup.as_deref())?;
+    Ok(true)
+}
+
+/// Writes a rewritten PNG where the output options point. The positional output file is an
+/// alias for --output. With --dry-run the chunk changes against the original file are printed
+/// instead, and no output target is needed.
+fn save(
+    original: &[u8],
+    png: &Png,
+    input: &Path,
+    output_file: Option<PathBuf>,
+    args: &OutputArgs,
+) -> Result<()> {
+    let output_path = output_file.or_else(|| args.output.clone());
+    let bytes = png.as_bytes();
+
+    if args.write.dry_run {
+        let target = match (output_path, args.in_place) {
+            (None, false) => String::new(),
+            (output_path, in_place) => {
+                let path = output::destination(input, output_path, in_place, args.write.force)?;
+                format!(" to {}", path.display())
+            }
+        };
+        let original = Png::try_from(original)?;
+        let changes = diff::chunk_changes(original.chunks(), png.chunks());
+        if changes.is_empty() {
+            eprintln!("No chunk changes");
+        }
+        for change in changes {
+            eprintln!("{}", change);
+        }
+        eprintln!("Dry run: would write {} bytes{}", bytes.len(), target);
+        return Ok(());
+    }
+
+    let output_path = output::destination(input, output_path, args.in_place, args.write.force)?;
+    write_file(&output_path, &bytes, args.write.backup.as_deref())
+}
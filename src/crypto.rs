@@ -0,0 +1,570 @@
+use crate::chunk_type::ChunkType;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use core::fmt;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+
+/// Envelope layout: magic, version, scheme, scheme parameters, nonce, then the ciphertext
+/// with its Poly1305 tag. Everything before the ciphertext is authenticated as associated data.
+const ENVELOPE_MAGIC: [u8; 4] = *b"PMEV";
+const ENVELOPE_VERSION: u8 = 1;
+/// Argon2id parameters (memory KiB, iterations, lanes as u32 BE) followed by the salt
+const SCHEME_PASSPHRASE: u8 = 1;
+const PASSPHRASE_PARAMS_LEN: usize = 12 + SALT_LEN;
+/// Ephemeral X25519 public key and a u16 BE recipient count, followed by the file key
+/// wrapped for each recipient
+const SCHEME_RECIPIENTS: u8 = 2;
+const RECIPIENTS_PARAMS_LEN: usize = KEY_LEN + 2;
+const WRAPPED_KEY_LEN: usize = KEY_LEN + 16;
+const PUBLIC_KEY_PREFIX: &str = "pngme-public-key:";
+const SECRET_KEY_PREFIX: &str = "pngme-secret-key:";
+const VERIFYING_KEY_PREFIX: &str = "pngme-verifying-key:";
+const SIGNING_KEY_PREFIX: &str = "pngme-signing-key:";
+/// Authenticated payloads carry this magic and an HMAC-SHA256 tag before the payload
+const TAG_MAGIC: [u8; 4] = *b"PMAC";
+const TAG_LEN: usize = 32;
+/// Refuses envelopes asking for more than 1 GiB of Argon2 memory
+const MAX_MEMORY_KIB: u32 = 1 << 20;
+/// Refuses envelopes asking for more than 16 Argon2 passes
+const MAX_TIME_COST: u32 = 16;
+/// Refuses envelopes asking for more than 16 Argon2 lanes
+const MAX_PARALLELISM: u32 = 16;
+
+#[derive(Debug)]
+pub enum CryptoError {
+    KeyDerivation(String),
+    Encryption(String),
+    InvalidEnvelope(String),
+    InvalidKey(String),
+    Decryption(String),
+    Authentication(String),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::KeyDerivation(e) => write!(f, "Crypto Error: {}", e),
+            CryptoError::Encryption(e) => write!(f, "Crypto Error: {}", e),
+            CryptoError::InvalidEnvelope(e) => write!(f, "Crypto Error: {}", e),
+            CryptoError::InvalidKey(e) => write!(f, "Crypto Error: {}", e),
+            CryptoError::Decryption(e) => write!(f, "Crypto Error: {}", e),
+            CryptoError::Authentication(e) => write!(f, "Crypto Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// Derives a 256-bit key from a passphrase with Argon2id using its default cost parameters
+pub fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_LEN]> {
+    derive_key_with_params(passphrase, salt, Params::default())
+}
+
+fn derive_key_with_params(passphrase: &str, salt: &[u8], params: Params) -> Result<[u8; KEY_LEN]> {
+    let mut key = [0; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+
+    Ok(key)
+}
+
+/// Returns true if the data starts like an envelope produced by this module
+fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(&ENVELOPE_MAGIC)
+}
+
+/// Encrypts a message with a key derived from the passphrase and a fresh random salt
+pub fn seal_with_passphrase(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let params = Params::default();
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut header = envelope_header(SCHEME_PASSPHRASE);
+    header.extend_from_slice(&params.m_cost().to_be_bytes());
+    header.extend_from_slice(&params.t_cost().to_be_bytes());
+    header.extend_from_slice(&params.p_cost().to_be_bytes());
+    header.extend_from_slice(&salt);
+
+    let key = derive_key_with_params(passphrase, &salt, params)?;
+    seal(&key, header, plaintext)
+}
+
+/// Decrypts an envelope sealed with `seal_with_passphrase`
+pub fn open_with_passphrase(passphrase: &str, envelope: &[u8]) -> Result<Vec<u8>> {
+    let params = scheme_params(envelope, SCHEME_PASSPHRASE, PASSPHRASE_PARAMS_LEN)?;
+    let cost = |i: usize| u32::from_be_bytes(params[i * 4..i * 4 + 4].try_into().unwrap());
+    let (m_cost, t_cost, p_cost) = (cost(0), cost(1), cost(2));
+    let salt = &params[12..];
+    if m_cost > MAX_MEMORY_KIB {
+        let error_message = format!("Envelope asks for {} KiB of Argon2 memory", m_cost);
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+    if t_cost > MAX_TIME_COST {
+        let error_message = format!("Envelope asks for {} Argon2 passes", t_cost);
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+    if p_cost > MAX_PARALLELISM {
+        let error_message = format!("Envelope asks for {} Argon2 lanes", p_cost);
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN))
+        .map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))?;
+
+    let key = derive_key_with_params(passphrase, salt, params)?;
+    open(&key, envelope, PASSPHRASE_PARAMS_LEN, "Wrong passphrase")
+}
+
+/// Returns true if the data starts with an HMAC tag added by `attach_tag`
+fn is_tagged(data: &[u8]) -> bool {
+    data.starts_with(&TAG_MAGIC)
+}
+
+/// Prefixes the payload with an HMAC-SHA256 tag over the chunk type, the frame flags and the
+/// payload, so a layer flag cannot be flipped without breaking the tag
+pub fn attach_tag(key: &[u8], chunk_type: &ChunkType, flags: u8, payload: &[u8]) -> Vec<u8> {
+    let tag = tag_mac(key, chunk_type, flags, payload)
+        .finalize()
+        .into_bytes();
+    [TAG_MAGIC.as_slice(), &tag, payload].concat()
+}
+
+/// Checks the tag added by `attach_tag` and returns the payload without it
+pub fn check_tag(key: &[u8], chunk_type: &ChunkType, flags: u8, data: &[u8]) -> Result<Vec<u8>> {
+    if !is_tagged(data) || data.len() < TAG_MAGIC.len() + TAG_LEN {
+        let error_message = "Message has no HMAC tag".to_string();
+        return Err(CryptoError::Authentication(error_message).into());
+    }
+
+    let (tag, payload) = data[TAG_MAGIC.len()..].split_at(TAG_LEN);
+    tag_mac(key, chunk_type, flags, payload)
+        .verify_slice(tag)
+        .map_err(|_| {
+            let error_message =
+                "HMAC tag does not match. The key is wrong or the message was tampered with"
+                    .to_string();
+            CryptoError::Authentication(error_message)
+        })?;
+
+    Ok(payload.to_vec())
+}
+
+fn tag_mac(key: &[u8], chunk_type: &ChunkType, flags: u8, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&chunk_type.bytes());
+    mac.update(&[flags]);
+    mac.update(payload);
+    mac
+}
+
+/// Creates a new X25519 identity for `open_with_identity`
+pub fn generate_identity() -> StaticSecret {
+    StaticSecret::random_from_rng(OsRng)
+}
+
+pub fn encode_public_key(public_key: &PublicKey) -> String {
+    format!("{}{}", PUBLIC_KEY_PREFIX, to_hex(public_key.as_bytes()))
+}
+
+pub fn encode_identity(identity: &StaticSecret) -> String {
+    format!("{}{}", SECRET_KEY_PREFIX, to_hex(identity.as_bytes()))
+}
+
+pub fn parse_public_key(text: &str) -> Result<PublicKey> {
+    Ok(PublicKey::from(parse_key(text, PUBLIC_KEY_PREFIX)?))
+}
+
+pub fn parse_identity(text: &str) -> Result<StaticSecret> {
+    Ok(StaticSecret::from(parse_key(text, SECRET_KEY_PREFIX)?))
+}
+
+/// Creates a new Ed25519 key for signing images
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::generate(&mut OsRng)
+}
+
+pub fn encode_verifying_key(verifying_key: &VerifyingKey) -> String {
+    format!(
+        "{}{}",
+        VERIFYING_KEY_PREFIX,
+        to_hex(verifying_key.as_bytes())
+    )
+}
+
+pub fn encode_signing_key(signing_key: &SigningKey) -> String {
+    format!("{}{}", SIGNING_KEY_PREFIX, to_hex(signing_key.as_bytes()))
+}
+
+pub fn parse_verifying_key(text: &str) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&parse_key(text, VERIFYING_KEY_PREFIX)?)
+        .map_err(|e| CryptoError::InvalidKey(e.to_string()).into())
+}
+
+pub fn parse_signing_key(text: &str) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&parse_key(
+        text,
+        SIGNING_KEY_PREFIX,
+    )?))
+}
+
+/// Encrypts a message under a random file key, wrapped for every recipient with a key agreed
+/// between a one-off ephemeral key and the recipient's public key
+pub fn seal_for_recipients(recipients: &[PublicKey], plaintext: &[u8]) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u16::MAX as usize {
+        let error_message = format!("Cannot encrypt for {} recipients", recipients.len());
+        return Err(CryptoError::Encryption(error_message).into());
+    }
+
+    let mut file_key = [0; KEY_LEN];
+    OsRng.fill_bytes(&mut file_key);
+    // A fresh key per envelope, kept as a StaticSecret to agree with several recipients
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+
+    let mut header = envelope_header(SCHEME_RECIPIENTS);
+    header.extend_from_slice(ephemeral_public.as_bytes());
+    header.extend_from_slice(&(recipients.len() as u16).to_be_bytes());
+
+    for recipient in recipients {
+        let shared = ephemeral.diffie_hellman(recipient);
+        if !shared.was_contributory() {
+            let error_message = "Recipient public key is a low-order point".to_string();
+            return Err(CryptoError::InvalidKey(error_message).into());
+        }
+
+        let wrap_key = wrap_key(shared.as_bytes(), &ephemeral_public, recipient)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&wrap_key));
+        let wrapped = cipher
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(|e| CryptoError::Encryption(e.to_string()))?;
+        header.extend_from_slice(&wrapped);
+    }
+
+    seal(&file_key, header, plaintext)
+}
+
+/// Decrypts an envelope sealed with `seal_for_recipients` if the identity is one of them
+pub fn open_with_identity(identity: &StaticSecret, envelope: &[u8]) -> Result<Vec<u8>> {
+    let params = scheme_params(envelope, SCHEME_RECIPIENTS, RECIPIENTS_PARAMS_LEN)?;
+    let ephemeral_public: [u8; KEY_LEN] = params[..KEY_LEN].try_into().unwrap();
+    let ephemeral_public = PublicKey::from(ephemeral_public);
+    let count = u16::from_be_bytes([params[KEY_LEN], params[KEY_LEN + 1]]) as usize;
+
+    let params_len = RECIPIENTS_PARAMS_LEN + count * WRAPPED_KEY_LEN;
+    let params = scheme_params(envelope, SCHEME_RECIPIENTS, params_len)?;
+
+    let shared = identity.diffie_hellman(&ephemeral_public);
+    let wrap_key = wrap_key(
+        shared.as_bytes(),
+        &ephemeral_public,
+        &PublicKey::from(identity),
+    )?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&wrap_key));
+
+    let file_key = params[RECIPIENTS_PARAMS_LEN..]
+        .chunks_exact(WRAPPED_KEY_LEN)
+        .find_map(|wrapped| cipher.decrypt(&Nonce::default(), wrapped).ok())
+        .ok_or_else(|| {
+            let error_message = "Message was not encrypted for this identity".to_string();
+            CryptoError::Decryption(error_message)
+        })?;
+
+    let file_key: [u8; KEY_LEN] = file_key.try_into().unwrap();
+    open(&file_key, envelope, params_len, "Wrong identity")
+}
+
+fn wrap_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> Result<[u8; KEY_LEN]> {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = [0; KEY_LEN];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(b"pngme x25519 file key", &mut key)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+
+    Ok(key)
+}
+
+fn parse_key(text: &str, prefix: &str) -> Result<[u8; KEY_LEN]> {
+    let text = text.trim();
+    let hex = text.strip_prefix(prefix).ok_or_else(|| {
+        let error_message = format!("Expected a key starting with {}", prefix);
+        CryptoError::InvalidKey(error_message)
+    })?;
+
+    let bytes = from_hex(hex).filter(|bytes| bytes.len() == KEY_LEN);
+    bytes
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            let error_message = format!("Expected {} hex-encoded key bytes", KEY_LEN);
+            CryptoError::InvalidKey(error_message).into()
+        })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn scheme_name(scheme: u8) -> String {
+    match scheme {
+        SCHEME_PASSPHRASE => "a passphrase".to_string(),
+        SCHEME_RECIPIENTS => "recipient keys".to_string(),
+        _ => format!("unknown scheme {}", scheme),
+    }
+}
+
+fn envelope_header(scheme: u8) -> Vec<u8> {
+    let mut header = ENVELOPE_MAGIC.to_vec();
+    header.push(ENVELOPE_VERSION);
+    header.push(scheme);
+    header
+}
+
+/// Checks the envelope prefix and returns the scheme parameters that follow it
+fn scheme_params(envelope: &[u8], scheme: u8, params_len: usize) -> Result<&[u8]> {
+    if !is_envelope(envelope) {
+        let error_message = "Message is not encrypted".to_string();
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+
+    let prefix_len = ENVELOPE_MAGIC.len() + 2;
+    if envelope.len() < prefix_len + params_len + NONCE_LEN {
+        let error_message = "Encrypted message is truncated".to_string();
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+    if envelope[4] != ENVELOPE_VERSION {
+        let error_message = format!("Unsupported envelope version {}", envelope[4]);
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+    if envelope[5] != scheme {
+        let error_message = format!(
+            "Message was encrypted with {}, not {}",
+            scheme_name(envelope[5]),
+            scheme_name(scheme)
+        );
+        return Err(CryptoError::InvalidEnvelope(error_message).into());
+    }
+
+    Ok(&envelope[prefix_len..prefix_len + params_len])
+}
+
+fn seal(key: &[u8; KEY_LEN], mut header: Vec<u8>, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    header.extend_from_slice(&nonce);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let payload = Payload {
+        msg: plaintext,
+        aad: &header,
+    };
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), payload)
+        .map_err(|e| CryptoError::Encryption(e.to_string()))?;
+
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+fn open(key: &[u8; KEY_LEN], envelope: &[u8], params_len: usize, hint: &str) -> Result<Vec<u8>> {
+    let header_len = ENVELOPE_MAGIC.len() + 2 + params_len + NONCE_LEN;
+    let (header, ciphertext) = envelope.split_at(header_len);
+    let nonce = &header[header_len - NONCE_LEN..];
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| {
+            let error_message = format!("{} or the message has been altered", hint);
+            CryptoError::Decryption(error_message).into()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_key_is_deterministic() {
+        let key = derive_key("passphrase", b"saltsalt").unwrap();
+        assert_eq!(key, derive_key("passphrase", b"saltsalt").unwrap());
+        assert_ne!(key, derive_key("passphrase", b"saltpepper").unwrap());
+        assert_ne!(key, derive_key("Passphrase", b"saltsalt").unwrap());
+    }
+
+    #[test]
+    fn test_derive_key_short_salt() {
+        assert!(derive_key("passphrase", b"salt").is_err());
+    }
+
+    #[test]
+    fn test_passphrase_round_trip() {
+        let envelope = seal_with_passphrase("passphrase", b"secret message").unwrap();
+        assert!(is_envelope(&envelope));
+        assert!(!envelope.windows(6).any(|w| w == b"secret"));
+
+        let plaintext = open_with_passphrase("passphrase", &envelope).unwrap();
+        assert_eq!(plaintext, b"secret message");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let envelope = seal_with_passphrase("passphrase", b"secret message").unwrap();
+        let error = open_with_passphrase("Passphrase", &envelope).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CryptoError>(),
+            Some(CryptoError::Decryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_excessive_argon2_costs() {
+        let envelope = seal_with_passphrase("passphrase", b"secret message").unwrap();
+        for offset in [6, 10, 14] {
+            let mut expensive = envelope.clone();
+            expensive[offset..offset + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+            let error = open_with_passphrase("passphrase", &expensive).unwrap_err();
+            assert!(error.to_string().contains("Envelope asks for"));
+        }
+    }
+
+    #[test]
+    fn test_tampered_envelope() {
+        let mut envelope = seal_with_passphrase("passphrase", b"secret message").unwrap();
+        let last = envelope.len() - 1;
+        envelope[last] ^= 1;
+        assert!(open_with_passphrase("passphrase", &envelope).is_err());
+    }
+
+    #[test]
+    fn test_recipients_round_trip() {
+        let alice = generate_identity();
+        let bob = generate_identity();
+        let eve = generate_identity();
+        let recipients = [PublicKey::from(&alice), PublicKey::from(&bob)];
+
+        let envelope = seal_for_recipients(&recipients, b"secret message").unwrap();
+        assert_eq!(
+            open_with_identity(&alice, &envelope).unwrap(),
+            b"secret message"
+        );
+        assert_eq!(
+            open_with_identity(&bob, &envelope).unwrap(),
+            b"secret message"
+        );
+
+        let error = open_with_identity(&eve, &envelope).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CryptoError>(),
+            Some(CryptoError::Decryption(_))
+        ));
+        assert!(open_with_passphrase("passphrase", &envelope).is_err());
+    }
+
+    #[test]
+    fn test_key_encoding() {
+        let identity = generate_identity();
+        let public_key = PublicKey::from(&identity);
+
+        let encoded = encode_public_key(&public_key);
+        assert!(encoded.starts_with(PUBLIC_KEY_PREFIX));
+        assert_eq!(
+            parse_public_key(&format!("{}\n", encoded)).unwrap(),
+            public_key
+        );
+
+        let encoded = encode_identity(&identity);
+        assert_eq!(
+            parse_identity(&encoded).unwrap().to_bytes(),
+            identity.to_bytes()
+        );
+
+        assert!(parse_identity(&encode_public_key(&public_key)).is_err());
+        assert!(parse_public_key("pngme-public-key:abcd").is_err());
+        assert!(parse_public_key("pngme-public-key:zz").is_err());
+    }
+
+    #[test]
+    fn test_signing_key_encoding() {
+        let signing_key = generate_signing_key();
+        let verifying_key = signing_key.verifying_key();
+
+        let encoded = encode_verifying_key(&verifying_key);
+        assert_eq!(parse_verifying_key(&encoded).unwrap(), verifying_key);
+
+        let encoded = encode_signing_key(&signing_key);
+        assert_eq!(parse_signing_key(&encoded).unwrap(), signing_key);
+        assert!(parse_identity(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_tag_round_trip() {
+        let rust = ChunkType::try_from(*b"ruSt").unwrap();
+        let tagged = attach_tag(b"shared key", &rust, 1, b"message");
+        assert!(is_tagged(&tagged));
+        assert_eq!(
+            check_tag(b"shared key", &rust, 1, &tagged).unwrap(),
+            b"message"
+        );
+    }
+
+    #[test]
+    fn test_tag_mismatch() {
+        let rust = ChunkType::try_from(*b"ruSt").unwrap();
+        let tagged = attach_tag(b"shared key", &rust, 1, b"message");
+
+        let mut tampered = tagged.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let other_type = ChunkType::try_from(*b"ruSX").unwrap();
+
+        for error in [
+            check_tag(b"other key", &rust, 1, &tagged).unwrap_err(),
+            check_tag(b"shared key", &rust, 1, &tampered).unwrap_err(),
+            check_tag(b"shared key", &other_type, 1, &tagged).unwrap_err(),
+            check_tag(b"shared key", &rust, 5, &tagged).unwrap_err(),
+            check_tag(b"shared key", &rust, 1, b"message").unwrap_err(),
+        ] {
+            assert!(matches!(
+                error.downcast_ref::<CryptoError>(),
+                Some(CryptoError::Authentication(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_invalid_envelope() {
+        let error = open_with_passphrase("passphrase", b"plain text").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CryptoError>(),
+            Some(CryptoError::InvalidEnvelope(_))
+        ));
+        assert!(open_with_passphrase("passphrase", b"PMEV\x01\x01short").is_err());
+    }
+}
@@ -0,0 +1,504 @@
+use crate::ihdr::{ColorType, ImageHeader};
+use crate::image_data::{ImageData, Pass};
+use crate::palette::{Palette, Transparency};
+use core::fmt;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Unfiltered, de-interlaced samples in the image's own color type and bit depth. Rows are
+/// `header.stride(header.width)` bytes long and carry no filter byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawImage {
+    pub header: ImageHeader,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pixels {
+    Rgba8(Vec<u8>),
+    Rgba16(Vec<u16>),
+}
+
+/// Fully decoded pixels, expanded to RGBA with 8 bits per sample, or 16 for 16-bit images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Pixels,
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    InvalidFilter(String),
+    InvalidPixel(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidFilter(e) => write!(f, "Decode Error: {}", e),
+            DecodeError::InvalidPixel(e) => write!(f, "Decode Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl RawImage {
+    /// Reverses the scanline filters and merges any Adam7 passes into full-size rows
+    pub fn from_image_data(image_data: &ImageData) -> Result<RawImage> {
+        let header = image_data.header;
+        let bits_per_pixel = header.bits_per_pixel();
+        let filter_bpp = (bits_per_pixel / 8).max(1);
+        let stride = header.stride(header.width);
+        let mut data = vec![0; stride * header.height as usize];
+
+        let mut offset = 0;
+        for pass in image_data.passes() {
+            let pass_stride = header.stride(pass.width);
+            let mut previous = vec![0; pass_stride];
+
+            for row in 0..pass.height {
+                let filter_type = image_data.raw[offset];
+                let mut current = image_data.raw[offset + 1..offset + 1 + pass_stride].to_vec();
+                unfilter(filter_type, filter_bpp, &previous, &mut current)?;
+
+                let y = (pass.y_start + row * pass.y_step) as usize;
+                let target = &mut data[y * stride..(y + 1) * stride];
+                if header.interlaced {
+                    scatter_row(&pass, bits_per_pixel, &current, target);
+                } else {
+                    target.copy_from_slice(&current);
+                }
+
+                previous = current;
+                offset += 1 + pass_stride;
+            }
+        }
+
+        Ok(RawImage {
+            header: ImageHeader {
+                interlaced: false,
+                ..header
+            },
+            data,
+        })
+    }
+
+    pub fn row(&self, y: u32) -> &[u8] {
+        let stride = self.header.stride(self.header.width);
+        &self.data[y as usize * stride..(y as usize + 1) * stride]
+    }
+
+    /// Expands every pixel to RGBA, resolving palette indices and tRNS color keys
+    pub fn to_rgba(
+        &self,
+        palette: Option<&Palette>,
+        transparency: Option<&Transparency>,
+    ) -> Result<DecodedImage> {
+        let header = &self.header;
+        if header.color_type == ColorType::Indexed && palette.is_none() {
+            let error_message = "Indexed-color image has no PLTE chunk".to_string();
+            return Err(DecodeError::InvalidPixel(error_message).into());
+        }
+
+        let channels = header.color_type.channels();
+        let pixel_count = header.width as usize * header.height as usize;
+        let mut rgba: Vec<u16> = Vec::with_capacity(pixel_count * 4);
+        let mut samples = vec![0u16; channels];
+
+        for y in 0..header.height {
+            let row = self.row(y);
+            for x in 0..header.width as usize {
+                for (channel, sample) in samples.iter_mut().enumerate() {
+                    *sample = read_sample(row, x * channels + channel, header.bit_depth);
+                }
+                rgba.extend_from_slice(&self.sample_to_rgba(&samples, palette, transparency)?);
+            }
+        }
+
+        let pixels = if header.bit_depth == 16 {
+            Pixels::Rgba16(rgba)
+        } else {
+            Pixels::Rgba8(rgba.into_iter().map(|value| value as u8).collect())
+        };
+
+        Ok(DecodedImage {
+            width: header.width,
+            height: header.height,
+            pixels,
+        })
+    }
+
+    /// Converts one pixel's samples to RGBA at 8 bits per sample, or 16 for 16-bit images
+    fn sample_to_rgba(
+        &self,
+        samples: &[u16],
+        palette: Option<&Palette>,
+        transparency: Option<&Transparency>,
+    ) -> Result<[u16; 4]> {
+        let bit_depth = self.header.bit_depth;
+        let max = if bit_depth == 16 { u16::MAX } else { 255 };
+        let scale = |value: u16| scale_sample(value, bit_depth);
+
+        let rgba = match self.header.color_type {
+            ColorType::Indexed => {
+                let index = samples[0] as usize;
+                let rgb = palette
+                    .and_then(|palette| palette.entries.get(index))
+                    .ok_or_else(|| {
+                        let error_message = format!("Palette index {} is out of range", index);
+                        DecodeError::InvalidPixel(error_message)
+                    })?;
+                let alpha = transparency.map_or(255, |t| t.alpha_for_index(index));
+                [
+                    rgb.red as u16,
+                    rgb.green as u16,
+                    rgb.blue as u16,
+                    alpha as u16,
+                ]
+            }
+            ColorType::Grayscale => {
+                let gray = samples[0];
+                let alpha = match transparency {
+                    Some(Transparency::Gray(key)) if *key == gray => 0,
+                    _ => max,
+                };
+                [scale(gray), scale(gray), scale(gray), alpha]
+            }
+            ColorType::Rgb => {
+                let alpha = match transparency {
+                    Some(Transparency::Rgb(r, g, b))
+                        if [*r, *g, *b] == [samples[0], samples[1], samples[2]] =>
+                    {
+                        0
+                    }
+                    _ => max,
+                };
+                [
+                    scale(samples[0]),
+                    scale(samples[1]),
+                    scale(samples[2]),
+                    alpha,
+                ]
+            }
+            ColorType::GrayscaleAlpha => {
+                let gray = scale(samples[0]);
+                [gray, gray, gray, scale(samples[1])]
+            }
+            ColorType::Rgba => [
+                scale(samples[0]),
+                scale(samples[1]),
+                scale(samples[2]),
+                scale(samples[3]),
+            ],
+        };
+
+        Ok(rgba)
+    }
+}
+
+impl DecodedImage {
+    /// Returns the pixel buffer as bytes, with 16-bit samples in big-endian order
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match &self.pixels {
+            Pixels::Rgba8(pixels) => pixels.clone(),
+            Pixels::Rgba16(pixels) => pixels.iter().flat_map(|v| v.to_be_bytes()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Pixels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pixels::Rgba8(_) => write!(f, "RGBA8"),
+            Pixels::Rgba16(_) => write!(f, "RGBA16"),
+        }
+    }
+}
+
+/// Reverses one of the five PNG filter types in place, using the previous unfiltered row
+pub fn unfilter(filter_type: u8, bpp: usize, previous: &[u8], current: &mut [u8]) -> Result<()> {
+    match filter_type {
+        0 => {}
+        1 => {
+            for i in bpp..current.len() {
+                current[i] = current[i].wrapping_add(current[i - bpp]);
+            }
+        }
+        2 => {
+            for (value, above) in current.iter_mut().zip(previous) {
+                *value = value.wrapping_add(*above);
+            }
+        }
+        3 => {
+            for i in 0..current.len() {
+                let left = if i >= bpp { current[i - bpp] } else { 0 };
+                let average = ((left as u16 + previous[i] as u16) / 2) as u8;
+                current[i] = current[i].wrapping_add(average);
+            }
+        }
+        4 => {
+            for i in 0..current.len() {
+                let left = if i >= bpp { current[i - bpp] } else { 0 };
+                let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+                current[i] = current[i].wrapping_add(paeth(left, previous[i], upper_left));
+            }
+        }
+        other => {
+            let error_message = format!("Unknown filter type {}", other);
+            return Err(DecodeError::InvalidFilter(error_message).into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_above = (estimate - above as i16).abs();
+    let distance_upper_left = (estimate - upper_left as i16).abs();
+
+    if distance_left <= distance_above && distance_left <= distance_upper_left {
+        left
+    } else if distance_above <= distance_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+/// Reads the sample at `index` from a packed row of samples at the given bit depth
+pub fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bit % 8;
+            let mask = (1u16 << bit_depth) - 1;
+            (row[bit / 8] as u16 >> shift) & mask
+        }
+    }
+}
+
+/// Scales a sample to 8 bits, or leaves 16-bit samples as they are
+fn scale_sample(value: u16, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 | 8 => value,
+        _ => value * 255 / ((1 << bit_depth) - 1),
+    }
+}
+
+/// Copies each pixel of a reduced pass row to its position in the full-size row
+fn scatter_row(pass: &Pass, bits_per_pixel: usize, source: &[u8], target: &mut [u8]) {
+    for i in 0..pass.width as usize {
+        let x = pass.x_start as usize + i * pass.x_step as usize;
+
+        if bits_per_pixel >= 8 {
+            let bytes = bits_per_pixel / 8;
+            target[x * bytes..(x + 1) * bytes].copy_from_slice(&source[i * bytes..(i + 1) * bytes]);
+        } else {
+            let bit_depth = bits_per_pixel as u8;
+            let value = read_sample(source, i, bit_depth) as u8;
+            let bit = x * bits_per_pixel;
+            let shift = 8 - bits_per_pixel - bit % 8;
+            let mask = (((1u16 << bits_per_pixel) - 1) as u8) << shift;
+            target[bit / 8] = (target[bit / 8] & !mask) | (value << shift);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::palette::Rgb;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    fn testing_header(
+        color_type: ColorType,
+        bit_depth: u8,
+        width: u32,
+        height: u32,
+    ) -> ImageHeader {
+        ImageHeader {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        }
+    }
+
+    fn image_data(header: ImageHeader, raw: &[u8]) -> ImageData {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw).unwrap();
+        let chunk = Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            encoder.finish().unwrap(),
+        );
+
+        ImageData::decompress(header, &[&chunk]).unwrap()
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+    }
+
+    #[test]
+    fn test_unfilter_all_types() {
+        let previous = [10, 20, 30, 40];
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(1, 1, &previous, &mut row).unwrap();
+        assert_eq!(row, [1, 3, 6, 10]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(2, 1, &previous, &mut row).unwrap();
+        assert_eq!(row, [11, 22, 33, 44]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(3, 1, &previous, &mut row).unwrap();
+        assert_eq!(row, [6, 15, 25, 36]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(4, 1, &previous, &mut row).unwrap();
+        assert_eq!(row, [11, 22, 33, 44]);
+
+        assert!(unfilter(5, 1, &previous, &mut row).is_err());
+    }
+
+    #[test]
+    fn test_read_sample() {
+        let row = [0b1011_0010, 0b0111_0000];
+        assert_eq!(read_sample(&row, 0, 1), 1);
+        assert_eq!(read_sample(&row, 1, 1), 0);
+        assert_eq!(read_sample(&row, 1, 2), 0b11);
+        assert_eq!(read_sample(&row, 1, 4), 0b0010);
+        assert_eq!(read_sample(&row, 0, 16), 0b1011_0010_0111_0000);
+    }
+
+    #[test]
+    fn test_decode_rgb8_with_filters() {
+        let header = testing_header(ColorType::Rgb, 8, 2, 2);
+        #[rustfmt::skip]
+        let raw = [
+            1, 10, 20, 30, 5, 5, 5,
+            2, 1, 1, 1, 1, 1, 1,
+        ];
+        let raw_image = RawImage::from_image_data(&image_data(header, &raw)).unwrap();
+        assert_eq!(
+            raw_image.data,
+            [10, 20, 30, 15, 25, 35, 11, 21, 31, 16, 26, 36]
+        );
+
+        let decoded = raw_image.to_rgba(None, None).unwrap();
+        assert_eq!(
+            decoded.pixels,
+            Pixels::Rgba8(vec![
+                10, 20, 30, 255, 15, 25, 35, 255, 11, 21, 31, 255, 16, 26, 36, 255
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_gray2_with_transparency() {
+        let header = testing_header(ColorType::Grayscale, 2, 4, 1);
+        let raw = [0, 0b00_01_10_11];
+        let raw_image = RawImage::from_image_data(&image_data(header, &raw)).unwrap();
+        let decoded = raw_image
+            .to_rgba(None, Some(&Transparency::Gray(2)))
+            .unwrap();
+
+        assert_eq!(
+            decoded.pixels,
+            Pixels::Rgba8(vec![
+                0, 0, 0, 255, 85, 85, 85, 255, 170, 170, 170, 0, 255, 255, 255, 255
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_indexed() {
+        let header = testing_header(ColorType::Indexed, 1, 2, 1);
+        let palette = Palette {
+            entries: vec![
+                Rgb::from_str("#ff0000").unwrap(),
+                Rgb::from_str("#00ff00").unwrap(),
+            ],
+        };
+        let raw_image = RawImage::from_image_data(&image_data(header, &[0, 0b0100_0000])).unwrap();
+        let decoded = raw_image
+            .to_rgba(Some(&palette), Some(&Transparency::Indexed(vec![128])))
+            .unwrap();
+
+        assert_eq!(
+            decoded.pixels,
+            Pixels::Rgba8(vec![255, 0, 0, 128, 0, 255, 0, 255])
+        );
+        assert!(raw_image.to_rgba(None, None).is_err());
+    }
+
+    #[test]
+    fn test_decode_rgba16() {
+        let header = testing_header(ColorType::GrayscaleAlpha, 16, 1, 1);
+        let raw_image = RawImage::from_image_data(&image_data(header, &[0, 1, 2, 3, 4])).unwrap();
+        let decoded = raw_image.to_rgba(None, None).unwrap();
+
+        assert_eq!(
+            decoded.pixels,
+            Pixels::Rgba16(vec![0x0102, 0x0102, 0x0102, 0x0304])
+        );
+        assert_eq!(decoded.as_bytes(), [1, 2, 1, 2, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_decode_adam7() {
+        // 3x3 grayscale image with pixel value 10 * y + x, stored as Adam7 passes
+        let header = ImageHeader {
+            interlaced: true,
+            ..testing_header(ColorType::Grayscale, 8, 3, 3)
+        };
+        #[rustfmt::skip]
+        let raw = [
+            0, 0,           // pass 1: (0, 0)
+            0, 2,           // pass 4: (2, 0)
+            0, 20, 22,      // pass 5: (0, 2), (2, 2)
+            0, 1,           // pass 6: (1, 0)
+            0, 21,          //         (1, 2)
+            0, 10, 11, 12,  // pass 7: row 1
+        ];
+        let raw_image = RawImage::from_image_data(&image_data(header, &raw)).unwrap();
+
+        assert!(!raw_image.header.interlaced);
+        assert_eq!(raw_image.data, [0, 1, 2, 10, 11, 12, 20, 21, 22]);
+    }
+
+    #[test]
+    fn test_decode_adam7_sub_byte() {
+        // 2x2 1-bit grayscale image with pixels [1, 0] / [0, 1]
+        let header = ImageHeader {
+            interlaced: true,
+            ..testing_header(ColorType::Grayscale, 1, 2, 2)
+        };
+        #[rustfmt::skip]
+        let raw = [
+            0, 0b1000_0000, // pass 1: (0, 0)
+            0, 0b0000_0000, // pass 6: (1, 0)
+            0, 0b0100_0000, // pass 7: row 1
+        ];
+        let raw_image = RawImage::from_image_data(&image_data(header, &raw)).unwrap();
+
+        assert_eq!(raw_image.data, [0b1000_0000, 0b0100_0000]);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use core::fmt;
+use std::collections::{HashMap, VecDeque};
+
+/// Largest table of old by new chunks the longest common subsequence is computed over, 16 MiB
+/// of u32 lengths. Bigger differences, such as between files with thousands of IDAT chunks
+/// each, are matched greedily by type and CRC instead.
+const MAX_TABLE_CELLS: usize = 1 << 22;
+
+/// Type, length and CRC of a chunk, enough to tell chunks apart in a report
+#[derive(Debug, PartialEq)]
+pub struct ChunkSummary {
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub crc: u32,
+}
+
+impl From<&Chunk> for ChunkSummary {
+    fn from(chunk: &Chunk) -> Self {
+        ChunkSummary {
+            chunk_type: chunk.chunk_type().clone(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+        }
+    }
+}
+
+impl fmt::Display for ChunkSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes, CRC {:08x}", self.length, self.crc)
+    }
+}
+
+/// A difference between the chunk lists of two PNG files. Removed chunks are indexed in the
+/// old file, added and replaced chunks in the new one.
+#[derive(Debug, PartialEq)]
+pub enum ChunkChange {
+    Added {
+        index: usize,
+        chunk: ChunkSummary,
+    },
+    Removed {
+        index: usize,
+        chunk: ChunkSummary,
+    },
+    Replaced {
+        index: usize,
+        old: ChunkSummary,
+        new: ChunkSummary,
+    },
+    Moved {
+        old_index: usize,
+        new_index: usize,
+        chunk: ChunkSummary,
+    },
+}
+
+impl fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkChange::Added { index, chunk } => {
+                write!(f, "+ #{} {}: {}", index, chunk.chunk_type, chunk)
+            }
+            ChunkChange::Removed { index, chunk } => {
+                write!(f, "- #{} {}: {}", index, chunk.chunk_type, chunk)
+            }
+            ChunkChange::Replaced { index, old, new } => {
+                write!(f, "~ #{} {}: {} -> {}", index, new.chunk_type, old, new)
+            }
+            ChunkChange::Moved {
+                old_index,
+                new_index,
+                chunk,
+            } => write!(
+                f,
+                "> #{} -> #{} {}: {}",
+                old_index, new_index, chunk.chunk_type, chunk
+            ),
+        }
+    }
+}
+
+/// Lists the chunks that differ between `old` and `new`. Unchanged chunks are matched with a
+/// longest common subsequence. Of the rest, identical chunks found in both lists count as
+/// moved, and within each unmatched run a removed and an added chunk of the same type count as
+/// one replacement.
+pub fn chunk_changes(old: &[Chunk], new: &[Chunk]) -> Vec<ChunkChange> {
+    let mut runs = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in common_chunks(old, new)
+        .into_iter()
+        .chain([(old.len(), new.len())])
+    {
+        if i < next_i || j < next_j {
+            runs.push((i..next_i, j..next_j));
+        }
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+
+    let mut new_used = vec![false; new.len()];
+    let mut moved_to = vec![None; old.len()];
+    for (old_run, _) in &runs {
+        for i in old_run.clone() {
+            let target = runs
+                .iter()
+                .flat_map(|(_, new_run)| new_run.clone())
+                .find(|&j| !new_used[j] && same_chunk(&old[i], &new[j]));
+            if let Some(j) = target {
+                new_used[j] = true;
+                moved_to[i] = Some(j);
+            }
+        }
+    }
+
+    let mut changes = vec![];
+    for (old_run, new_run) in runs {
+        for i in old_run {
+            if let Some(j) = moved_to[i] {
+                changes.push(ChunkChange::Moved {
+                    old_index: i,
+                    new_index: j,
+                    chunk: ChunkSummary::from(&old[i]),
+                });
+                continue;
+            }
+
+            let partner = new_run
+                .clone()
+                .find(|&j| !new_used[j] && new[j].chunk_type() == old[i].chunk_type());
+            match partner {
+                Some(j) => {
+                    new_used[j] = true;
+                    changes.push(ChunkChange::Replaced {
+                        index: j,
+                        old: ChunkSummary::from(&old[i]),
+                        new: ChunkSummary::from(&new[j]),
+                    });
+                }
+                None => changes.push(ChunkChange::Removed {
+                    index: i,
+                    chunk: ChunkSummary::from(&old[i]),
+                }),
+            }
+        }
+
+        for j in new_run {
+            if !new_used[j] {
+                changes.push(ChunkChange::Added {
+                    index: j,
+                    chunk: ChunkSummary::from(&new[j]),
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+fn same_chunk(a: &Chunk, b: &Chunk) -> bool {
+    a.chunk_type() == b.chunk_type() && a.data() == b.data()
+}
+
+/// Index pairs of the chunks both lists keep in the same order. A common prefix and suffix are
+/// matched directly, so only the part in between pays for the quadratic table, or for the
+/// greedy match when the table would be too large.
+fn common_chunks(old: &[Chunk], new: &[Chunk]) -> Vec<(usize, usize)> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| same_chunk(a, b))
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| same_chunk(a, b))
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let middle = if old_middle.len().saturating_mul(new_middle.len()) > MAX_TABLE_CELLS {
+        greedy_common(old_middle, new_middle)
+    } else {
+        longest_common(old_middle, new_middle)
+    };
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    pairs.extend(middle.into_iter().map(|(i, j)| (prefix + i, prefix + j)));
+    pairs.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+    pairs
+}
+
+fn longest_common(old: &[Chunk], new: &[Chunk]) -> Vec<(usize, usize)> {
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if same_chunk(&old[i], &new[j]) {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if same_chunk(&old[i], &new[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Matches each old chunk to the first identical new chunk after the previous match, in
+/// linear time and space. Not always the longest match, but close for files that mostly keep
+/// their chunk order.
+fn greedy_common(old: &[Chunk], new: &[Chunk]) -> Vec<(usize, usize)> {
+    let mut positions: HashMap<([u8; 4], u32), VecDeque<usize>> = HashMap::new();
+    for (j, chunk) in new.iter().enumerate() {
+        positions
+            .entry((chunk.chunk_type().bytes(), chunk.crc()))
+            .or_default()
+            .push_back(j);
+    }
+
+    let mut pairs = vec![];
+    let mut next_j = 0;
+    for (i, chunk) in old.iter().enumerate() {
+        let Some(queue) = positions.get_mut(&(chunk.chunk_type().bytes(), chunk.crc())) else {
+            continue;
+        };
+        while queue.front().is_some_and(|&j| j < next_j) {
+            queue.pop_front();
+        }
+        if let Some(j) = queue
+            .front()
+            .copied()
+            .filter(|&j| same_chunk(chunk, &new[j]))
+        {
+            queue.pop_front();
+            pairs.push((i, j));
+            next_j = j + 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    fn chunks(types: &[(&str, &str)]) -> Vec<Chunk> {
+        types.iter().map(|(t, d)| chunk(t, d)).collect()
+    }
+
+    #[test]
+    fn test_chunk_changes_identical() {
+        let old = chunks(&[("IHDR", "h"), ("IDAT", "d"), ("IEND", "")]);
+        assert!(chunk_changes(&old, &old.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_changes_added_and_removed() {
+        let old = chunks(&[("IHDR", "h"), ("tEXt", "a"), ("IDAT", "d"), ("IEND", "")]);
+        let new = chunks(&[("IHDR", "h"), ("IDAT", "d"), ("ruSt", "msg"), ("IEND", "")]);
+
+        let changes = chunk_changes(&old, &new);
+        assert_eq!(
+            changes,
+            vec![
+                ChunkChange::Removed {
+                    index: 1,
+                    chunk: ChunkSummary::from(&old[1]),
+                },
+                ChunkChange::Added {
+                    index: 2,
+                    chunk: ChunkSummary::from(&new[2]),
+                },
+            ]
+        );
+        assert_eq!(
+            changes[1].to_string(),
+            format!("+ #2 ruSt: 3 bytes, CRC {:08x}", new[2].crc())
+        );
+    }
+
+    #[test]
+    fn test_chunk_changes_replaced() {
+        let old = chunks(&[("IHDR", "h"), ("pHYs", "old"), ("IDAT", "d"), ("IEND", "")]);
+        let new = chunks(&[
+            ("IHDR", "h"),
+            ("pHYs", "newer"),
+            ("IDAT", "d"),
+            ("IEND", ""),
+        ]);
+
+        let changes = chunk_changes(&old, &new);
+        assert_eq!(
+            changes,
+            vec![ChunkChange::Replaced {
+                index: 1,
+                old: ChunkSummary::from(&old[1]),
+                new: ChunkSummary::from(&new[1]),
+            }]
+        );
+        assert!(changes[0].to_string().starts_with("~ #1 pHYs: 3 bytes"));
+    }
+
+    #[test]
+    fn test_chunk_changes_without_table() {
+        let old: Vec<Chunk> = (0..3000).map(|i| chunk("IDAT", &i.to_string())).collect();
+        let mut new = old.clone();
+        new[0] = chunk("IDAT", "first");
+        new[2999] = chunk("IDAT", "last");
+        assert!(old.len() * new.len() > MAX_TABLE_CELLS);
+
+        let changes = chunk_changes(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert!(
+            changes
+                .iter()
+                .all(|change| matches!(change, ChunkChange::Replaced { .. }))
+        );
+    }
+
+    #[test]
+    fn test_chunk_changes_moved() {
+        let old = chunks(&[("IHDR", "h"), ("IDAT", "d"), ("tEXt", "a"), ("IEND", "")]);
+        let new = chunks(&[("IHDR", "h"), ("tEXt", "a"), ("IDAT", "d"), ("IEND", "")]);
+
+        let changes = chunk_changes(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(
+            changes[0],
+            ChunkChange::Moved {
+                old_index: 1,
+                new_index: 2,
+                ..
+            } | ChunkChange::Moved {
+                old_index: 2,
+                new_index: 1,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::decoder::{RawImage, paeth};
+use crate::ihdr::{ColorType, ImageHeader};
+use crate::palette::{Palette, Transparency};
+use crate::png::Png;
+use core::fmt;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+use std::str::FromStr;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Produces a PNG from unfiltered pixel data laid out as in `RawImage`: packed samples in the
+/// image's color type and bit depth, one row of `header.stride(width)` bytes after another.
+#[derive(Debug)]
+pub struct PngEncoder {
+    header: ImageHeader,
+    idat_chunk_size: usize,
+    compression: Compression,
+    palette: Option<Palette>,
+    transparency: Option<Transparency>,
+}
+
+#[derive(Debug)]
+pub enum EncodeError {
+    InvalidPixels(String),
+    InvalidOption(String),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::InvalidPixels(e) => write!(f, "Encode Error: {}", e),
+            EncodeError::InvalidOption(e) => write!(f, "Encode Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl PngEncoder {
+    pub const DEFAULT_IDAT_CHUNK_SIZE: usize = 8192;
+
+    pub fn new(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: u8,
+    ) -> Result<PngEncoder> {
+        Ok(PngEncoder {
+            header: ImageHeader::new(width, height, color_type, bit_depth)?,
+            idat_chunk_size: Self::DEFAULT_IDAT_CHUNK_SIZE,
+            compression: Compression::default(),
+            palette: None,
+            transparency: None,
+        })
+    }
+
+    /// Creates an encoder matching the format of a decoded image
+    pub fn for_raw_image(raw_image: &RawImage) -> Result<PngEncoder> {
+        let header = raw_image.header;
+        PngEncoder::new(
+            header.width,
+            header.height,
+            header.color_type,
+            header.bit_depth,
+        )
+    }
+
+    /// Sets the maximum number of compressed bytes stored in each IDAT chunk
+    pub fn with_idat_chunk_size(mut self, idat_chunk_size: usize) -> Result<PngEncoder> {
+        if idat_chunk_size == 0 || idat_chunk_size > i32::MAX as usize {
+            let error_message = format!("IDAT chunk size {} is out of range", idat_chunk_size);
+            return Err(EncodeError::InvalidOption(error_message).into());
+        }
+        self.idat_chunk_size = idat_chunk_size;
+
+        Ok(self)
+    }
+
+    /// Sets the zlib compression level, from 0 (store) to 9 (best)
+    pub fn with_compression_level(mut self, level: u32) -> Result<PngEncoder> {
+        if level > 9 {
+            let error_message = format!("Compression level {} is out of range", level);
+            return Err(EncodeError::InvalidOption(error_message).into());
+        }
+        self.compression = Compression::new(level);
+
+        Ok(self)
+    }
+
+    pub fn with_palette(mut self, palette: Palette) -> Result<PngEncoder> {
+        palette.validate(&self.header)?;
+        self.palette = Some(palette);
+
+        Ok(self)
+    }
+
+    pub fn with_transparency(mut self, transparency: Transparency) -> Result<PngEncoder> {
+        transparency.validate(&self.header, self.palette.as_ref())?;
+        self.transparency = Some(transparency);
+
+        Ok(self)
+    }
+
+    /// Compresses the image data into IDAT chunks, choosing a filter for every scanline
+    pub fn encode_image_data(&self, pixels: &[u8]) -> Result<Vec<Chunk>> {
+        let stride = self.header.stride(self.header.width);
+        let expected_len = stride * self.header.height as usize;
+        if pixels.len() != expected_len {
+            let error_message = format!(
+                "Expected {} bytes of pixel data for a {} image, found {}",
+                expected_len,
+                self.header,
+                pixels.len()
+            );
+            return Err(EncodeError::InvalidPixels(error_message).into());
+        }
+
+        let filter_bpp = (self.header.bits_per_pixel() / 8).max(1);
+        let adaptive = self.header.color_type != ColorType::Indexed && self.header.bit_depth >= 8;
+
+        let mut filtered = Vec::with_capacity(expected_len + self.header.height as usize);
+        let mut previous = vec![0; stride];
+        for row in pixels.chunks_exact(stride) {
+            let (filter_type, data) = if adaptive {
+                choose_filter(filter_bpp, &previous, row)
+            } else {
+                (0, row.to_vec())
+            };
+            filtered.push(filter_type);
+            filtered.extend_from_slice(&data);
+            previous = row.to_vec();
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), self.compression);
+        encoder.write_all(&filtered)?;
+        let compressed = encoder.finish()?;
+
+        let idat = ChunkType::from_str("IDAT")?;
+        Ok(compressed
+            .chunks(self.idat_chunk_size)
+            .map(|data| Chunk::new(idat.clone(), data.to_vec()))
+            .collect())
+    }
+
+    /// Builds a complete PNG with IHDR, optional PLTE and tRNS, IDAT and IEND chunks
+    pub fn encode(&self, pixels: &[u8]) -> Result<Png> {
+        if self.header.color_type == ColorType::Indexed && self.palette.is_none() {
+            let error_message = "Indexed-color images require a palette".to_string();
+            return Err(EncodeError::InvalidOption(error_message).into());
+        }
+
+        let mut chunks = vec![self.header.to_chunk()];
+        if let Some(palette) = &self.palette {
+            chunks.push(palette.to_chunk());
+        }
+        if let Some(transparency) = &self.transparency {
+            chunks.push(transparency.to_chunk());
+        }
+        chunks.extend(self.encode_image_data(pixels)?);
+        chunks.push(Chunk::new(ChunkType::from_str("IEND")?, vec![]));
+
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+/// Applies one of the five PNG filter types to a row, using the previous unfiltered row
+pub fn filter(filter_type: u8, bpp: usize, previous: &[u8], current: &[u8]) -> Vec<u8> {
+    (0..current.len())
+        .map(|i| {
+            let left = if i >= bpp { current[i - bpp] } else { 0 };
+            let above = previous[i];
+            let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+            let prediction = match filter_type {
+                1 => left,
+                2 => above,
+                3 => ((left as u16 + above as u16) / 2) as u8,
+                4 => paeth(left, above, upper_left),
+                _ => 0,
+            };
+            current[i].wrapping_sub(prediction)
+        })
+        .collect()
+}
+
+/// Picks the filter whose output has the smallest sum of absolute values, treating bytes as
+/// signed. This is the heuristic recommended by the PNG specification.
+fn choose_filter(bpp: usize, previous: &[u8], current: &[u8]) -> (u8, Vec<u8>) {
+    (0..=4)
+        .map(|filter_type| (filter_type, filter(filter_type, bpp, previous, current)))
+        .min_by_key(|(_, data)| {
+            data.iter()
+                .map(|byte| (*byte as i8).unsigned_abs() as u64)
+                .sum::<u64>()
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{Pixels, unfilter};
+    use crate::palette::Rgb;
+
+    fn gradient(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let previous = gradient(12);
+        let current: Vec<u8> = previous.iter().rev().copied().collect();
+
+        for filter_type in 0..=4 {
+            let mut row = filter(filter_type, 3, &previous, &current);
+            unfilter(filter_type, 3, &previous, &mut row).unwrap();
+            assert_eq!(row, current);
+        }
+    }
+
+    #[test]
+    fn test_choose_filter_prefers_up_for_repeated_rows() {
+        let row = gradient(12);
+        let (filter_type, data) = choose_filter(3, &row, &row);
+        assert_eq!(filter_type, 2);
+        assert!(data.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_encode_round_trip_rgba8() {
+        let pixels = gradient(7 * 5 * 4);
+        let png = PngEncoder::new(7, 5, ColorType::Rgba, 8)
+            .unwrap()
+            .with_idat_chunk_size(16)
+            .unwrap()
+            .encode(&pixels)
+            .unwrap();
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types.first().unwrap(), "IHDR");
+        assert_eq!(types.last().unwrap(), "IEND");
+        assert!(types.iter().filter(|t| *t == "IDAT").count() > 1);
+        assert!(
+            png.chunks()
+                .iter()
+                .all(|c| c.length() <= 16 || c.chunk_type().to_string() != "IDAT")
+        );
+
+        let bytes = png.as_bytes();
+        let png = Png::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.decode().unwrap().pixels, Pixels::Rgba8(pixels));
+    }
+
+    #[test]
+    fn test_encode_round_trip_gray16() {
+        let pixels = gradient(3 * 3 * 2);
+        let png = PngEncoder::new(3, 3, ColorType::Grayscale, 16)
+            .unwrap()
+            .encode(&pixels)
+            .unwrap();
+
+        assert_eq!(png.raw_image().unwrap().data, pixels);
+    }
+
+    #[test]
+    fn test_encode_indexed() {
+        let palette = Palette {
+            entries: vec![
+                Rgb::from_str("#000000").unwrap(),
+                Rgb::from_str("#ffffff").unwrap(),
+            ],
+        };
+        let encoder = PngEncoder::new(9, 2, ColorType::Indexed, 1).unwrap();
+        assert!(encoder.encode(&[0; 4]).is_err());
+
+        let png = encoder
+            .with_palette(palette)
+            .unwrap()
+            .with_transparency(Transparency::Indexed(vec![0]))
+            .unwrap()
+            .encode(&[0b1010_1010, 0b1000_0000, 0b0101_0101, 0])
+            .unwrap();
+
+        assert!(png.chunk_by_type("PLTE").is_some());
+        assert!(png.chunk_by_type("tRNS").is_some());
+        let decoded = png.decode().unwrap();
+        match decoded.pixels {
+            Pixels::Rgba8(pixels) => {
+                assert_eq!(&pixels[0..8], &[255, 255, 255, 255, 0, 0, 0, 0]);
+            }
+            Pixels::Rgba16(_) => panic!("Expected 8-bit pixels"),
+        }
+    }
+
+    #[test]
+    fn test_encode_wrong_pixel_length() {
+        let encoder = PngEncoder::new(2, 2, ColorType::Rgb, 8).unwrap();
+        assert!(encoder.encode(&[0; 11]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let encoder = PngEncoder::new(2, 2, ColorType::Rgb, 8).unwrap();
+        assert!(encoder.with_idat_chunk_size(0).is_err());
+
+        let encoder = PngEncoder::new(2, 2, ColorType::Rgb, 8).unwrap();
+        assert!(encoder.with_compression_level(10).is_err());
+
+        assert!(PngEncoder::new(2, 2, ColorType::Rgb, 4).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+/// Bytes shown on each line of a hex dump
+const LINE_WIDTH: usize = 16;
+
+/// Formats bytes like `hexdump -C`: an offset, sixteen hex bytes split into two groups of
+/// eight, and the printable ASCII characters with everything else shown as a dot
+pub fn hexdump(data: &[u8]) -> String {
+    let mut lines = Vec::new();
+
+    for (line_index, line) in data.chunks(LINE_WIDTH).enumerate() {
+        let mut hex = String::new();
+        for position in 0..LINE_WIDTH {
+            if position == LINE_WIDTH / 2 {
+                hex.push(' ');
+            }
+            match line.get(position) {
+                Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+                None => hex.push_str("   "),
+            }
+        }
+
+        let ascii: String = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        lines.push(format!(
+            "{:08x}  {} |{}|",
+            line_index * LINE_WIDTH,
+            hex,
+            ascii
+        ));
+    }
+    lines.push(format!("{:08x}", data.len()));
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump_lines() {
+        let data: Vec<u8> = b"\x89PNG\r\n\x1a\n"
+            .iter()
+            .chain(b"hello, world!!")
+            .copied()
+            .collect();
+        let dump = hexdump(&data);
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "00000000  89 50 4e 47 0d 0a 1a 0a  68 65 6c 6c 6f 2c 20 77  |.PNG....hello, w|"
+        );
+        assert_eq!(
+            lines[1],
+            "00000010  6f 72 6c 64 21 21                                 |orld!!|"
+        );
+        assert_eq!(lines[2], "00000016");
+    }
+
+    #[test]
+    fn test_hexdump_empty() {
+        assert_eq!(hexdump(&[]), "00000000");
+    }
+}
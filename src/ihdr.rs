@@ -0,0 +1,306 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use core::fmt;
+use std::str::FromStr;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+/// Typed contents of an IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+#[derive(Debug)]
+pub enum HeaderError {
+    InvalidChunk(String),
+    Missing(String),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::InvalidChunk(e) => write!(f, "IHDR Error: {}", e),
+            HeaderError::Missing(e) => write!(f, "IHDR Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl ColorType {
+    pub fn from_byte(byte: u8) -> Option<ColorType> {
+        match byte {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Number of samples stored for each pixel
+    pub fn channels(self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths allowed for this color type by the PNG specification
+    pub fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "Grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "Indexed",
+            ColorType::GrayscaleAlpha => "Grayscale + Alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ImageHeader {
+    pub const CHUNK_TYPE: &'static str = "IHDR";
+    /// Largest width or height the specification allows, 2^31 - 1
+    pub const MAX_DIMENSION: u32 = i32::MAX as u32;
+
+    pub fn new(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: u8,
+    ) -> Result<ImageHeader> {
+        let header = ImageHeader {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        };
+        header.validate()?;
+
+        Ok(header)
+    }
+
+    /// Number of bits used by a single pixel
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Number of bytes in one unfiltered scanline of the given width, excluding the filter byte
+    pub fn stride(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Largest sample value representable at this header's bit depth
+    pub fn max_sample_value(&self) -> u16 {
+        ((1u32 << self.bit_depth) - 1) as u16
+    }
+
+    pub fn to_chunk(self) -> Chunk {
+        let mut data = Vec::with_capacity(13);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.push(self.bit_depth);
+        data.push(self.color_type.as_byte());
+        data.push(0);
+        data.push(0);
+        data.push(self.interlaced as u8);
+
+        Chunk::new(ChunkType::from_str(Self::CHUNK_TYPE).unwrap(), data)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            let error_message = "Image dimensions must be non-zero".to_string();
+            return Err(HeaderError::InvalidChunk(error_message).into());
+        }
+        if self.width > Self::MAX_DIMENSION || self.height > Self::MAX_DIMENSION {
+            let error_message = format!(
+                "Image dimensions must be at most {}, found {}x{}",
+                Self::MAX_DIMENSION,
+                self.width,
+                self.height
+            );
+            return Err(HeaderError::InvalidChunk(error_message).into());
+        }
+
+        if !self
+            .color_type
+            .allowed_bit_depths()
+            .contains(&self.bit_depth)
+        {
+            let error_message = format!(
+                "Bit depth {} is not allowed for color type {}",
+                self.bit_depth, self.color_type
+            );
+            return Err(HeaderError::InvalidChunk(error_message).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&Chunk> for ImageHeader {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != Self::CHUNK_TYPE {
+            let error_message = format!("Expected an IHDR chunk, found {}", chunk.chunk_type());
+            return Err(HeaderError::InvalidChunk(error_message).into());
+        }
+
+        let data = chunk.data();
+        if data.len() != 13 {
+            let error_message = format!("IHDR data must be 13 bytes, found {}", data.len());
+            return Err(HeaderError::InvalidChunk(error_message).into());
+        }
+
+        let color_type = ColorType::from_byte(data[9]).ok_or_else(|| {
+            let error_message = format!("Unknown color type {}", data[9]);
+            HeaderError::InvalidChunk(error_message)
+        })?;
+
+        if data[10] != 0 || data[11] != 0 || data[12] > 1 {
+            let error_message = "Unsupported compression, filter or interlace method".to_string();
+            return Err(HeaderError::InvalidChunk(error_message).into());
+        }
+
+        let header = ImageHeader {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type,
+            interlaced: data[12] == 1,
+        };
+        header.validate()?;
+
+        Ok(header)
+    }
+}
+
+impl fmt::Display for ImageHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {} {}-bit{}",
+            self.width,
+            self.height,
+            self.color_type,
+            self.bit_depth,
+            if self.interlaced { " interlaced" } else { "" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_chunk(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Chunk {
+        let data: Vec<u8> = width
+            .to_be_bytes()
+            .iter()
+            .chain(height.to_be_bytes().iter())
+            .chain([bit_depth, color_type, 0, 0, 0].iter())
+            .copied()
+            .collect();
+
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+    }
+
+    #[test]
+    fn test_header_from_chunk() {
+        let header = ImageHeader::try_from(&header_chunk(50, 40, 8, 6)).unwrap();
+        assert_eq!((header.width, header.height), (50, 40));
+        assert_eq!(header.bit_depth, 8);
+        assert_eq!(header.color_type, ColorType::Rgba);
+        assert!(!header.interlaced);
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = ImageHeader::new(50, 40, ColorType::Indexed, 4).unwrap();
+        let chunk = header.to_chunk();
+        assert_eq!(chunk.length(), 13);
+
+        let actual = ImageHeader::try_from(&chunk).unwrap();
+        assert_eq!(actual, header);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        assert!(ImageHeader::try_from(&header_chunk(1, 1, 4, 2)).is_err());
+        assert!(ImageHeader::try_from(&header_chunk(1, 1, 16, 3)).is_err());
+        assert!(ImageHeader::try_from(&header_chunk(1, 1, 3, 0)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_dimensions() {
+        assert!(ImageHeader::try_from(&header_chunk(0, 1, 8, 2)).is_err());
+        assert!(ImageHeader::try_from(&header_chunk(1, 1 << 31, 8, 2)).is_err());
+        assert!(ImageHeader::try_from(&header_chunk(i32::MAX as u32, 1, 8, 2)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_color_type() {
+        assert!(ImageHeader::try_from(&header_chunk(1, 1, 8, 5)).is_err());
+    }
+
+    #[test]
+    fn test_stride() {
+        let header = ImageHeader::try_from(&header_chunk(10, 1, 1, 3)).unwrap();
+        assert_eq!(header.stride(10), 2);
+
+        let header = ImageHeader::try_from(&header_chunk(10, 1, 16, 6)).unwrap();
+        assert_eq!(header.stride(10), 80);
+        assert_eq!(header.stride(3), 24);
+    }
+
+    #[test]
+    fn test_max_sample_value() {
+        let header = ImageHeader::try_from(&header_chunk(1, 1, 4, 0)).unwrap();
+        assert_eq!(header.max_sample_value(), 15);
+
+        let header = ImageHeader::try_from(&header_chunk(1, 1, 16, 0)).unwrap();
+        assert_eq!(header.max_sample_value(), u16::MAX);
+    }
+}
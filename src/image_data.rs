@@ -0,0 +1,283 @@
+use crate::chunk::Chunk;
+use crate::ihdr::ImageHeader;
+use core::fmt;
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Origin and spacing of the seven Adam7 passes as (x start, y start, x step, y step)
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// A reduced image making up part of the image data. Non-interlaced images have a single
+/// pass covering every pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub x_start: u32,
+    pub y_start: u32,
+    pub x_step: u32,
+    pub y_step: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A single filtered scanline, as stored in the decompressed IDAT stream
+#[derive(Debug, PartialEq, Eq)]
+pub struct Scanline<'a> {
+    pub pass: usize,
+    pub filter_type: u8,
+    pub data: &'a [u8],
+}
+
+/// The decompressed contents of all IDAT chunks in an image
+#[derive(Debug)]
+pub struct ImageData {
+    pub header: ImageHeader,
+    pub compressed_len: usize,
+    pub idat_count: usize,
+    pub raw: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum ImageDataError {
+    Missing(String),
+    Decompression(String),
+    InvalidLength(String),
+}
+
+impl fmt::Display for ImageDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageDataError::Missing(e) => write!(f, "Image Data Error: {}", e),
+            ImageDataError::Decompression(e) => write!(f, "Image Data Error: {}", e),
+            ImageDataError::InvalidLength(e) => write!(f, "Image Data Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageDataError {}
+
+impl Pass {
+    /// Returns the passes used to store an image with the given header, skipping empty ones
+    pub fn for_header(header: &ImageHeader) -> Vec<Pass> {
+        if !header.interlaced {
+            return vec![Pass {
+                x_start: 0,
+                y_start: 0,
+                x_step: 1,
+                y_step: 1,
+                width: header.width,
+                height: header.height,
+            }];
+        }
+
+        ADAM7_PASSES
+            .iter()
+            .map(|&(x_start, y_start, x_step, y_step)| Pass {
+                x_start,
+                y_start,
+                x_step,
+                y_step,
+                width: header.width.saturating_sub(x_start).div_ceil(x_step),
+                height: header.height.saturating_sub(y_start).div_ceil(y_step),
+            })
+            .filter(|pass| pass.width > 0 && pass.height > 0)
+            .collect()
+    }
+}
+
+impl ImageData {
+    /// Concatenates the IDAT chunks and inflates the zlib stream they contain
+    pub fn decompress(header: ImageHeader, idat_chunks: &[&Chunk]) -> Result<ImageData> {
+        if idat_chunks.is_empty() {
+            let error_message = "IDAT chunk not found.".to_string();
+            return Err(ImageDataError::Missing(error_message).into());
+        }
+
+        let compressed: Vec<u8> = idat_chunks
+            .iter()
+            .flat_map(|chunk| chunk.data())
+            .copied()
+            .collect();
+
+        let expected_len = Self::expected_len(&header)?;
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&compressed[..])
+            .take(expected_len as u64 + 1)
+            .read_to_end(&mut raw)
+            .map_err(|e| ImageDataError::Decompression(e.to_string()))?;
+
+        if raw.len() != expected_len {
+            let error_message = format!(
+                "Expected {} bytes of filtered scanlines for a {} image, found {}",
+                expected_len,
+                header,
+                raw.len()
+            );
+            return Err(ImageDataError::InvalidLength(error_message).into());
+        }
+
+        Ok(ImageData {
+            header,
+            compressed_len: compressed.len(),
+            idat_count: idat_chunks.len(),
+            raw,
+        })
+    }
+
+    /// Number of bytes the filtered scanlines of an image with this header occupy
+    pub fn expected_len(header: &ImageHeader) -> Result<usize> {
+        let too_large = || -> Error {
+            let error_message = format!("A {} image is too large to decode", header);
+            ImageDataError::InvalidLength(error_message).into()
+        };
+
+        Pass::for_header(header)
+            .iter()
+            .try_fold(0usize, |total, pass| {
+                (1 + header.stride(pass.width))
+                    .checked_mul(pass.height as usize)
+                    .and_then(|len| total.checked_add(len))
+                    .ok_or_else(too_large)
+            })
+    }
+
+    /// Number of bytes in one full-width scanline, excluding the filter byte
+    pub fn stride(&self) -> usize {
+        self.header.stride(self.header.width)
+    }
+
+    pub fn passes(&self) -> Vec<Pass> {
+        Pass::for_header(&self.header)
+    }
+
+    /// Splits the raw data into scanlines, in the order they are stored
+    pub fn scanlines(&self) -> Vec<Scanline<'_>> {
+        let mut result = Vec::new();
+        let mut offset = 0;
+
+        for (index, pass) in self.passes().iter().enumerate() {
+            let stride = self.header.stride(pass.width);
+            for _ in 0..pass.height {
+                result.push(Scanline {
+                    pass: index,
+                    filter_type: self.raw[offset],
+                    data: &self.raw[offset + 1..offset + 1 + stride],
+                });
+                offset += 1 + stride;
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::ColorType;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    fn testing_header(width: u32, height: u32, interlaced: bool) -> ImageHeader {
+        ImageHeader {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            interlaced,
+        }
+    }
+
+    fn idat_chunks(raw: &[u8], pieces: usize) -> Vec<Chunk> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        compressed
+            .chunks(compressed.len().div_ceil(pieces))
+            .map(|data| Chunk::new(ChunkType::from_str("IDAT").unwrap(), data.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_decompress_split_idat() {
+        let header = testing_header(2, 2, false);
+        let raw = vec![0, 1, 2, 3, 4, 5, 6, 1, 7, 8, 9, 10, 11, 12];
+        let chunks = idat_chunks(&raw, 3);
+        let chunk_refs: Vec<&Chunk> = chunks.iter().collect();
+
+        let image_data = ImageData::decompress(header, &chunk_refs).unwrap();
+        assert_eq!(image_data.raw, raw);
+        assert_eq!(image_data.idat_count, 3);
+        assert_eq!(image_data.stride(), 6);
+
+        let scanlines = image_data.scanlines();
+        assert_eq!(scanlines.len(), 2);
+        assert_eq!(scanlines[1].filter_type, 1);
+        assert_eq!(scanlines[1].data, &[7, 8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn test_decompress_wrong_length() {
+        let header = testing_header(2, 2, false);
+        let chunks = idat_chunks(&[0, 1, 2, 3], 1);
+        let chunk_refs: Vec<&Chunk> = chunks.iter().collect();
+
+        assert!(ImageData::decompress(header, &chunk_refs).is_err());
+    }
+
+    #[test]
+    fn test_decompress_invalid_stream() {
+        let header = testing_header(1, 1, false);
+        let chunk = Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![1, 2, 3]);
+
+        assert!(ImageData::decompress(header, &[&chunk]).is_err());
+        assert!(ImageData::decompress(header, &[]).is_err());
+    }
+
+    #[test]
+    fn test_adam7_passes() {
+        let passes = Pass::for_header(&testing_header(8, 8, true));
+        let sizes: Vec<(u32, u32)> = passes.iter().map(|p| (p.width, p.height)).collect();
+        assert_eq!(
+            sizes,
+            vec![(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+        );
+
+        let passes = Pass::for_header(&testing_header(1, 1, true));
+        assert_eq!(passes.len(), 1);
+    }
+
+    #[test]
+    fn test_expected_len_interlaced() {
+        // A 3x3 RGB image stores passes 1, 4, 5, 6 and 7 with sizes 1x1, 1x1, 2x1, 1x2, 3x1
+        let header = testing_header(3, 3, true);
+        assert_eq!(
+            ImageData::expected_len(&header).unwrap(),
+            4 + 4 + 7 + 2 * 4 + 10
+        );
+    }
+
+    #[test]
+    fn test_expected_len_overflow() {
+        let header = ImageHeader {
+            bit_depth: 16,
+            color_type: ColorType::Rgba,
+            ..testing_header(i32::MAX as u32, i32::MAX as u32, false)
+        };
+        assert!(ImageData::expected_len(&header).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::decoder::RawImage;
+use crate::ihdr::{ColorType, ImageHeader};
+use core::fmt;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Bytes stored ahead of the message: the chunk type used as a tag, then the message length
+const HEADER_LEN: usize = 8;
+
+/// Where and how densely message bits are stored in the pixel samples
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsbOptions {
+    pub bits_per_channel: u8,
+    pub channels: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum LsbError {
+    Unsupported(String),
+    InvalidOption(String),
+    Capacity(String),
+    NotFound(String),
+}
+
+impl fmt::Display for LsbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LsbError::Unsupported(e) => write!(f, "LSB Error: {}", e),
+            LsbError::InvalidOption(e) => write!(f, "LSB Error: {}", e),
+            LsbError::Capacity(e) => write!(f, "LSB Error: {}", e),
+            LsbError::NotFound(e) => write!(f, "LSB Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LsbError {}
+
+impl LsbOptions {
+    /// Resolves a channel mask such as `rgb` against the channels of the image. Grayscale
+    /// channels are named `y`. Without a mask every color channel is used, leaving alpha intact.
+    pub fn new(
+        header: &ImageHeader,
+        bits_per_channel: u8,
+        mask: Option<&str>,
+    ) -> Result<LsbOptions> {
+        if header.color_type == ColorType::Indexed || header.bit_depth < 8 {
+            let error_message = format!(
+                "LSB embedding needs 8 or 16-bit samples, found a {} image",
+                header
+            );
+            return Err(LsbError::Unsupported(error_message).into());
+        }
+
+        if bits_per_channel == 0 || bits_per_channel > 8 {
+            let error_message = format!(
+                "Bits per channel must be between 1 and 8, found {}",
+                bits_per_channel
+            );
+            return Err(LsbError::InvalidOption(error_message).into());
+        }
+
+        let names = channel_names(header.color_type);
+        let channels = match mask {
+            Some(mask) => {
+                let mut channels = Vec::new();
+                for name in mask.chars() {
+                    let channel = names.find(name.to_ascii_lowercase()).ok_or_else(|| {
+                        let error_message = format!(
+                            "Channel '{}' does not exist in a {} image, expected some of '{}'",
+                            name, header.color_type, names
+                        );
+                        LsbError::InvalidOption(error_message)
+                    })?;
+                    if !channels.contains(&channel) {
+                        channels.push(channel);
+                    }
+                }
+                channels.sort();
+                channels
+            }
+            None => names
+                .char_indices()
+                .filter(|(_, name)| *name != 'a')
+                .map(|(channel, _)| channel)
+                .collect(),
+        };
+
+        if channels.is_empty() {
+            let error_message = "At least one channel must be selected".to_string();
+            return Err(LsbError::InvalidOption(error_message).into());
+        }
+
+        Ok(LsbOptions {
+            bits_per_channel,
+            channels,
+        })
+    }
+}
+
+/// Number of message bytes that fit in the image with the given options
+pub fn capacity(raw_image: &RawImage, options: &LsbOptions) -> usize {
+    let bits =
+        pixel_count(&raw_image.header) * options.channels.len() * options.bits_per_channel as usize;
+    (bits / 8).saturating_sub(HEADER_LEN)
+}
+
+/// Hides a message in the least-significant bits of the selected channels, visiting pixels
+/// in the given order. The chunk type is stored with the message so it can be found again.
+pub fn embed(
+    raw_image: &mut RawImage,
+    options: &LsbOptions,
+    pixel_order: &[usize],
+    tag: &ChunkType,
+    message: &[u8],
+) -> Result<()> {
+    let available = capacity(raw_image, options);
+    if message.len() > available || message.len() > u32::MAX as usize {
+        let error_message = format!(
+            "Message is {} bytes but the image can only hold {} with these options",
+            message.len(),
+            available
+        );
+        return Err(LsbError::Capacity(error_message).into());
+    }
+
+    let mut payload = Vec::with_capacity(HEADER_LEN + message.len());
+    payload.extend_from_slice(&tag.chunk_type);
+    payload.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    payload.extend_from_slice(message);
+
+    let bits = options.bits_per_channel as usize;
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut reader = BitReader::new(&payload);
+    let offsets = sample_offsets(&raw_image.header, options, pixel_order);
+
+    for offset in offsets {
+        if reader.is_done() {
+            break;
+        }
+        let value = reader.read(bits);
+        raw_image.data[offset] = (raw_image.data[offset] & !mask) | value;
+    }
+
+    Ok(())
+}
+
+/// Recovers a message hidden by `embed` with the same options, pixel order and tag
+pub fn extract(
+    raw_image: &RawImage,
+    options: &LsbOptions,
+    pixel_order: &[usize],
+    tag: &ChunkType,
+) -> Result<Vec<u8>> {
+    let bits = options.bits_per_channel as usize;
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut offsets = sample_offsets(&raw_image.header, options, pixel_order).into_iter();
+    let mut read_bytes = |count: usize| -> Option<Vec<u8>> {
+        let mut writer = BitWriter::new(count);
+        while !writer.is_full() {
+            let offset = offsets.next()?;
+            writer.write(raw_image.data[offset] & mask, bits);
+        }
+        Some(writer.into_bytes())
+    };
+
+    let not_found = || {
+        let error_message = format!("No LSB message with chunk type {} found", tag);
+        LsbError::NotFound(error_message)
+    };
+
+    let header = read_bytes(HEADER_LEN).ok_or_else(not_found)?;
+    if header[0..4] != tag.chunk_type {
+        return Err(not_found().into());
+    }
+
+    let length = u32::from_be_bytes(header[4..8].try_into()?) as usize;
+    if length > capacity(raw_image, options) {
+        return Err(not_found().into());
+    }
+
+    let message = read_bytes(length).ok_or_else(not_found)?;
+
+    Ok(message)
+}
+
+/// Visits pixels from top-left to bottom-right
+pub fn sequential_order(header: &ImageHeader) -> Vec<usize> {
+    (0..pixel_count(header)).collect()
+}
+
+/// Visits pixels in a pseudo-random order seeded from a passphrase, so the message bits are
+/// scattered over the image and can only be located with the same passphrase. The salt is
+/// fixed per image size because there is nowhere to store a random one.
+///
+/// The shuffle is a Fisher-Yates over the raw ChaCha20 output rather than a library shuffle,
+/// whose algorithm may change between versions and would lose every embedded message.
+pub fn keyed_order(header: &ImageHeader, passphrase: &str) -> Result<Vec<usize>> {
+    if pixel_count(header) > u32::MAX as usize {
+        let error_message = format!("A {} image has too many pixels for a keyed order", header);
+        return Err(LsbError::Unsupported(error_message).into());
+    }
+
+    let mut salt = b"pngme-lsb-order".to_vec();
+    salt.extend_from_slice(&header.width.to_be_bytes());
+    salt.extend_from_slice(&header.height.to_be_bytes());
+    let seed = crypto::derive_key(passphrase, &salt)?;
+
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    let mut order = sequential_order(header);
+    for i in (1..order.len()).rev() {
+        let j = uniform_below(&mut rng, i as u32 + 1);
+        order.swap(i, j as usize);
+    }
+
+    Ok(order)
+}
+
+/// Draws a number below `bound` without modulo bias by rejecting the incomplete last range
+fn uniform_below(rng: &mut ChaCha20Rng, bound: u32) -> u32 {
+    let zone = u32::MAX / bound * bound;
+    loop {
+        let value = rng.next_u32();
+        if value < zone {
+            return value % bound;
+        }
+    }
+}
+
+fn pixel_count(header: &ImageHeader) -> usize {
+    header.width as usize * header.height as usize
+}
+
+fn channel_names(color_type: ColorType) -> &'static str {
+    match color_type {
+        ColorType::Grayscale | ColorType::Indexed => "y",
+        ColorType::GrayscaleAlpha => "ya",
+        ColorType::Rgb => "rgb",
+        ColorType::Rgba => "rgba",
+    }
+}
+
+/// Byte offsets of the least-significant byte of each selected sample, in embedding order
+fn sample_offsets(header: &ImageHeader, options: &LsbOptions, pixel_order: &[usize]) -> Vec<usize> {
+    let channels = header.color_type.channels();
+    let sample_bytes = header.bit_depth as usize / 8;
+
+    pixel_order
+        .iter()
+        .flat_map(|pixel| {
+            options
+                .channels
+                .iter()
+                .map(move |channel| (pixel * channels + channel) * sample_bytes + sample_bytes - 1)
+        })
+        .collect()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, position: 0 }
+    }
+
+    fn is_done(&self) -> bool {
+        self.position >= self.data.len() * 8
+    }
+
+    /// Reads up to `count` bits, most significant first, padding with zeros past the end
+    fn read(&mut self, count: usize) -> u8 {
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = self
+                .data
+                .get(self.position / 8)
+                .map_or(0, |byte| (byte >> (7 - self.position % 8)) & 1);
+            value = (value << 1) | bit;
+            self.position += 1;
+        }
+        value
+    }
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl BitWriter {
+    fn new(len: usize) -> BitWriter {
+        BitWriter {
+            data: vec![0; len],
+            position: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.position >= self.data.len() * 8
+    }
+
+    /// Writes the low `count` bits of `value`, most significant first, ignoring overflow
+    fn write(&mut self, value: u8, count: usize) {
+        for i in (0..count).rev() {
+            if self.is_full() {
+                return;
+            }
+            let bit = (value >> i) & 1;
+            self.data[self.position / 8] |= bit << (7 - self.position % 8);
+            self.position += 1;
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_image(color_type: ColorType, bit_depth: u8, width: u32, height: u32) -> RawImage {
+        let header = ImageHeader::new(width, height, color_type, bit_depth).unwrap();
+        let len = header.stride(width) * height as usize;
+        RawImage {
+            header,
+            data: (0..len).map(|i| (i * 31 % 256) as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        let mut image = testing_image(ColorType::Rgb, 8, 10, 10);
+        let original = image.clone();
+        let options = LsbOptions::new(&image.header, 1, None).unwrap();
+        let order = sequential_order(&image.header);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+
+        embed(&mut image, &options, &order, &tag, b"hidden").unwrap();
+        assert!(
+            image
+                .data
+                .iter()
+                .zip(&original.data)
+                .all(|(a, b)| a >> 1 == b >> 1)
+        );
+
+        let message = extract(&image, &options, &order, &tag).unwrap();
+        assert_eq!(message, b"hidden");
+    }
+
+    #[test]
+    fn test_embed_16_bit_uses_low_byte() {
+        let mut image = testing_image(ColorType::GrayscaleAlpha, 16, 8, 8);
+        let original = image.clone();
+        let options = LsbOptions::new(&image.header, 4, Some("ya")).unwrap();
+        let order = sequential_order(&image.header);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+
+        embed(&mut image, &options, &order, &tag, b"sixteen bits").unwrap();
+        for (i, (a, b)) in image.data.iter().zip(&original.data).enumerate() {
+            if i % 2 == 0 {
+                assert_eq!(a, b);
+            }
+        }
+
+        let message = extract(&image, &options, &order, &tag).unwrap();
+        assert_eq!(message, b"sixteen bits");
+    }
+
+    #[test]
+    fn test_channel_mask_leaves_other_channels() {
+        let mut image = testing_image(ColorType::Rgba, 8, 8, 8);
+        let original = image.clone();
+        let options = LsbOptions::new(&image.header, 2, Some("b")).unwrap();
+        let order = sequential_order(&image.header);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+
+        embed(&mut image, &options, &order, &tag, b"blue").unwrap();
+        for (i, (a, b)) in image.data.iter().zip(&original.data).enumerate() {
+            if i % 4 != 2 {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let image = testing_image(ColorType::Rgb, 8, 10, 10);
+        let options = LsbOptions::new(&image.header, 1, None).unwrap();
+        assert_eq!(capacity(&image, &options), 300 / 8 - 8);
+
+        let mut image = image;
+        let order = sequential_order(&image.header);
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        let message = vec![0; capacity(&image, &options) + 1];
+        assert!(embed(&mut image, &options, &order, &tag, &message).is_err());
+    }
+
+    #[test]
+    fn test_extract_wrong_tag() {
+        let mut image = testing_image(ColorType::Rgb, 8, 10, 10);
+        let options = LsbOptions::new(&image.header, 1, None).unwrap();
+        let order = sequential_order(&image.header);
+
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        embed(&mut image, &options, &order, &tag, b"hidden").unwrap();
+
+        let other = ChunkType::from_str("RuSa").unwrap();
+        assert!(extract(&image, &options, &order, &other).is_err());
+    }
+
+    #[test]
+    fn test_keyed_order() {
+        let image = testing_image(ColorType::Rgb, 8, 10, 10);
+        let order = keyed_order(&image.header, "correct horse").unwrap();
+
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, sequential_order(&image.header));
+        assert_ne!(order, sequential_order(&image.header));
+        assert_eq!(order, keyed_order(&image.header, "correct horse").unwrap());
+        assert_ne!(order, keyed_order(&image.header, "battery staple").unwrap());
+        // Pinned so a change to the shuffle cannot silently break existing images
+        assert_eq!(order[..8], [25, 56, 70, 78, 28, 47, 1, 74]);
+    }
+
+    #[test]
+    fn test_embed_with_keyed_order() {
+        let mut image = testing_image(ColorType::Rgb, 8, 10, 10);
+        let options = LsbOptions::new(&image.header, 1, None).unwrap();
+        let tag = ChunkType::from_str("RuSt").unwrap();
+        let order = keyed_order(&image.header, "correct horse").unwrap();
+
+        embed(&mut image, &options, &order, &tag, b"scattered").unwrap();
+        assert_eq!(
+            extract(&image, &options, &order, &tag).unwrap(),
+            b"scattered"
+        );
+
+        let wrong_order = keyed_order(&image.header, "battery staple").unwrap();
+        assert!(extract(&image, &options, &wrong_order, &tag).is_err());
+        let sequential = sequential_order(&image.header);
+        assert!(extract(&image, &options, &sequential, &tag).is_err());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let header = testing_image(ColorType::Rgb, 8, 1, 1).header;
+        assert!(LsbOptions::new(&header, 0, None).is_err());
+        assert!(LsbOptions::new(&header, 9, None).is_err());
+        assert!(LsbOptions::new(&header, 1, Some("a")).is_err());
+        assert!(LsbOptions::new(&header, 1, Some("")).is_err());
+
+        let header = ImageHeader::new(1, 1, ColorType::Indexed, 8).unwrap();
+        assert!(LsbOptions::new(&header, 1, None).is_err());
+    }
+
+    #[test]
+    fn test_default_channels_skip_alpha() {
+        let header = ImageHeader::new(1, 1, ColorType::Rgba, 8).unwrap();
+        let options = LsbOptions::new(&header, 1, None).unwrap();
+        assert_eq!(options.channels, vec![0, 1, 2]);
+
+        let options = LsbOptions::new(&header, 1, Some("AbR")).unwrap();
+        assert_eq!(options.channels, vec![0, 2, 3]);
+    }
+}
@@ -1,10 +1,27 @@
+mod analyze;
 mod args;
 mod chunk;
 mod chunk_type;
 mod commands;
+mod crypto;
+mod decoder;
+mod diff;
+mod encoder;
+mod hexdump;
+mod ihdr;
+mod image_data;
+mod lsb;
+mod metadata;
+mod output;
+mod palette;
+mod payload;
+mod phys;
 mod png;
-use args::PngMeArgs;
+mod report;
+mod signature;
+use args::{DpiCommand, IdatCommand, PaletteCommand, PngMeArgs};
 use clap::Parser;
+use std::process::ExitCode;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -16,7 +33,7 @@ pub struct Cli {
     command: PngMeArgs,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
     let result = match cli.command {
@@ -24,11 +41,37 @@ fn main() -> Result<()> {
         PngMeArgs::Decode(args) => commands::decode(args),
         PngMeArgs::Remove(args) => commands::remove(args),
         PngMeArgs::Print(args) => commands::print_chunks(args),
+        PngMeArgs::Dpi(args) => match args.command {
+            DpiCommand::Get(args) => commands::dpi_get(args),
+            DpiCommand::Set(args) => commands::dpi_set(args),
+        },
+        PngMeArgs::Palette(args) => match args.command {
+            PaletteCommand::Dump(args) => commands::palette_dump(args),
+            PaletteCommand::SetEntry(args) => commands::palette_set_entry(args),
+        },
+        PngMeArgs::Idat(args) => match args.command {
+            IdatCommand::Info(args) => commands::idat_info(args),
+            IdatCommand::Decode(args) => commands::idat_decode(args),
+            IdatCommand::Encode(args) => commands::idat_encode(args),
+        },
+        PngMeArgs::Analyze(args) => commands::analyze(args),
+        PngMeArgs::Keygen(args) => commands::keygen(args),
+        PngMeArgs::Sign(args) => commands::sign(args),
+        PngMeArgs::Verify(args) => commands::verify(args),
+        PngMeArgs::Diff(args) => commands::diff(args),
+        PngMeArgs::Dump(args) => commands::dump(args),
+        PngMeArgs::Extract(args) => commands::extract(args),
+        PngMeArgs::Inject(args) => commands::inject(args),
+        PngMeArgs::CopyMetadata(args) => commands::copy_metadata(args),
+        PngMeArgs::Strip(args) => commands::strip(args),
     };
 
-    if let Err(e) = result {
-        println!("{}", e)
+    // stdout may carry a PNG stream, so errors go to stderr and fail the process
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
     }
-
-    Ok(())
 }
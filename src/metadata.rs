@@ -0,0 +1,372 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use core::fmt;
+
+/// Known chunk types that may appear more than once in a file
+const REPEATABLE_TYPES: [&str; 12] = [
+    "IDAT", "tEXt", "zTXt", "iTXt", "sPLT", "fcTL", "fdAT", "gIFg", "gIFx", "gIFt", "fRAc", "dSIG",
+];
+
+/// Chunk types whose contents are only meaningful for the color type, bit depth and palette of
+/// the file they came from
+const FORMAT_DEPENDENT_TYPES: [&str; 4] = ["tRNS", "bKGD", "hIST", "sBIT"];
+
+/// Color space chunks kept by StripPreset::KeepColor
+const COLOR_TYPES: [&str; 4] = ["gAMA", "cHRM", "sRGB", "iCCP"];
+
+/// Chunk types that can carry personal information, removed by StripPreset::Privacy along with
+/// private chunks
+const PRIVACY_TYPES: [&str; 5] = ["tEXt", "zTXt", "iTXt", "eXIf", "tIME"];
+
+/// Which chunks strip removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripPreset {
+    /// Every ancillary chunk
+    AllAncillary,
+    /// Every ancillary chunk except the color space ones
+    KeepColor,
+    /// Text, Exif, timestamps and private chunks
+    Privacy,
+}
+
+/// What copy_metadata did with one ancillary chunk of the source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyAction {
+    Inserted,
+    Replaced,
+    AlreadyPresent,
+    NotSafeToCopy,
+    Excluded,
+    Incompatible,
+}
+
+#[derive(Debug)]
+pub struct CopyResult {
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub action: CopyAction,
+}
+
+impl fmt::Display for CopyAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self {
+            CopyAction::Inserted => "inserted",
+            CopyAction::Replaced => "replaced",
+            CopyAction::AlreadyPresent => "skipped, already present",
+            CopyAction::NotSafeToCopy => "skipped, not safe to copy",
+            CopyAction::Excluded => "skipped, excluded",
+            CopyAction::Incompatible => "skipped, color type, bit depth or palette differ",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+impl fmt::Display for CopyResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} bytes): {}",
+            self.chunk_type, self.length, self.action
+        )
+    }
+}
+
+/// Copies the ancillary chunks of `source` into `destination`. Without `include`, only chunks
+/// marked safe to copy are taken. With it, exactly the listed types are taken, whatever their
+/// safe-to-copy bit says. Types in `exclude` are always left out.
+///
+/// Chunks are inserted at a legal position relative to PLTE and IDAT. A known type that may
+/// only appear once replaces the destination's own chunk of that type. tRNS, bKGD, hIST and
+/// sBIT are only copied between files with the same color type, bit depth and palette.
+pub fn copy_metadata(
+    source: &Png,
+    destination: &mut Png,
+    include: &[ChunkType],
+    exclude: &[ChunkType],
+) -> Vec<CopyResult> {
+    let same_format = same_pixel_format(source, destination);
+    source
+        .chunks()
+        .iter()
+        .filter(|chunk| !chunk.chunk_type().is_critical())
+        .map(|chunk| {
+            let chunk_type = chunk.chunk_type();
+            let action = if exclude.contains(chunk_type)
+                || (!include.is_empty() && !include.contains(chunk_type))
+            {
+                CopyAction::Excluded
+            } else if include.is_empty() && !chunk_type.is_safe_to_copy() {
+                CopyAction::NotSafeToCopy
+            } else if !same_format
+                && FORMAT_DEPENDENT_TYPES.contains(&chunk_type.to_string().as_str())
+            {
+                CopyAction::Incompatible
+            } else {
+                copy_chunk(chunk, destination)
+            };
+
+            CopyResult {
+                chunk_type: chunk_type.clone(),
+                length: chunk.length(),
+                action,
+            }
+        })
+        .collect()
+}
+
+impl StripPreset {
+    pub fn removes(&self, chunk_type: &ChunkType) -> bool {
+        let name = chunk_type.to_string();
+        match self {
+            StripPreset::AllAncillary => !chunk_type.is_critical(),
+            StripPreset::KeepColor => {
+                !chunk_type.is_critical() && !COLOR_TYPES.contains(&name.as_str())
+            }
+            StripPreset::Privacy => {
+                PRIVACY_TYPES.contains(&name.as_str())
+                    || (!chunk_type.is_critical() && !chunk_type.is_public())
+            }
+        }
+    }
+}
+
+/// Removes the chunks the preset selects and returns them in file order
+pub fn strip(png: &mut Png, preset: StripPreset) -> Vec<Chunk> {
+    let (removed, kept) = png
+        .chunks
+        .drain(..)
+        .partition(|chunk| preset.removes(chunk.chunk_type()));
+    png.chunks = kept;
+    removed
+}
+
+/// Returns true for known chunk types the specification allows only once per file. A new chunk
+/// of such a type replaces the existing one instead of being added next to it.
+pub fn is_single_instance(chunk_type: &ChunkType) -> bool {
+    chunk_type.is_known() && !REPEATABLE_TYPES.contains(&chunk_type.to_string().as_str())
+}
+
+fn same_pixel_format(source: &Png, destination: &Png) -> bool {
+    let (Ok(source_header), Ok(destination_header)) = (source.header(), destination.header())
+    else {
+        return false;
+    };
+    let palette = |png: &Png| png.chunk_by_type("PLTE").map(|chunk| chunk.data().to_vec());
+
+    source_header.color_type == destination_header.color_type
+        && source_header.bit_depth == destination_header.bit_depth
+        && palette(source) == palette(destination)
+}
+
+fn copy_chunk(chunk: &Chunk, destination: &mut Png) -> CopyAction {
+    let existing = destination.chunks_by_type(&chunk.chunk_type().to_string());
+    if existing.iter().any(|other| other.data() == chunk.data()) {
+        return CopyAction::AlreadyPresent;
+    }
+
+    let is_single = is_single_instance(chunk.chunk_type());
+    let action = if is_single && !existing.is_empty() {
+        CopyAction::Replaced
+    } else {
+        CopyAction::Inserted
+    };
+    destination.insert_chunk(chunk.clone(), is_single);
+    action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, ImageHeader};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    fn png(chunks: &[(&str, &str)]) -> Png {
+        Png::from_chunks(chunks.iter().map(|(t, d)| chunk(t, d)).collect())
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    fn actions(results: &[CopyResult]) -> Vec<(String, CopyAction)> {
+        results
+            .iter()
+            .map(|r| (r.chunk_type.to_string(), r.action))
+            .collect()
+    }
+
+    #[test]
+    fn test_strip_presets() {
+        let chunks = [
+            ("IHDR", "h"),
+            ("gAMA", "g"),
+            ("sRGB", "s"),
+            ("pHYs", "p"),
+            ("IDAT", "d"),
+            ("tEXt", "t"),
+            ("tIME", "0000000"),
+            ("prVt", "x"),
+            ("IEND", ""),
+        ];
+
+        let mut all = png(&chunks);
+        let removed = strip(&mut all, StripPreset::AllAncillary);
+        assert_eq!(removed.len(), 6);
+        assert_eq!(types(&all), ["IHDR", "IDAT", "IEND"]);
+
+        let mut color = png(&chunks);
+        strip(&mut color, StripPreset::KeepColor);
+        assert_eq!(types(&color), ["IHDR", "gAMA", "sRGB", "IDAT", "IEND"]);
+
+        let mut privacy = png(&chunks);
+        strip(&mut privacy, StripPreset::Privacy);
+        assert_eq!(
+            types(&privacy),
+            ["IHDR", "gAMA", "sRGB", "pHYs", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_copy_metadata_safe_to_copy() {
+        let source = png(&[
+            ("IHDR", "h"),
+            ("gAMA", "g"),
+            ("pHYs", "p"),
+            ("IDAT", "d"),
+            ("tEXt", "t"),
+            ("IEND", ""),
+        ]);
+        let mut destination = png(&[("IHDR", "x"), ("PLTE", "x"), ("IDAT", "x"), ("IEND", "")]);
+
+        let results = copy_metadata(&source, &mut destination, &[], &[]);
+        assert_eq!(
+            actions(&results),
+            [
+                ("gAMA".to_string(), CopyAction::NotSafeToCopy),
+                ("pHYs".to_string(), CopyAction::Inserted),
+                ("tEXt".to_string(), CopyAction::Inserted),
+            ]
+        );
+        assert_eq!(
+            types(&destination),
+            ["IHDR", "PLTE", "pHYs", "IDAT", "tEXt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_copy_metadata_include_and_exclude() {
+        let source = png(&[
+            ("IHDR", "h"),
+            ("gAMA", "g"),
+            ("pHYs", "p"),
+            ("IDAT", "d"),
+            ("tEXt", "t"),
+            ("IEND", ""),
+        ]);
+        let mut destination = png(&[("IHDR", "x"), ("PLTE", "x"), ("IDAT", "x"), ("IEND", "")]);
+        let include = [
+            ChunkType::from_str("gAMA").unwrap(),
+            ChunkType::from_str("tEXt").unwrap(),
+        ];
+        let exclude = [ChunkType::from_str("tEXt").unwrap()];
+
+        let results = copy_metadata(&source, &mut destination, &include, &exclude);
+        assert_eq!(
+            actions(&results),
+            [
+                ("gAMA".to_string(), CopyAction::Inserted),
+                ("pHYs".to_string(), CopyAction::Excluded),
+                ("tEXt".to_string(), CopyAction::Excluded),
+            ]
+        );
+        assert_eq!(
+            types(&destination),
+            ["IHDR", "gAMA", "PLTE", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_copy_metadata_format_dependent_chunks() {
+        let rgb = ImageHeader::new(2, 2, ColorType::Rgb, 8)
+            .unwrap()
+            .to_chunk();
+        let indexed = ImageHeader::new(2, 2, ColorType::Indexed, 8)
+            .unwrap()
+            .to_chunk();
+        let source = Png::from_chunks(vec![
+            indexed.clone(),
+            chunk("PLTE", "abc"),
+            chunk("tRNS", "t"),
+            chunk("IDAT", "d"),
+            chunk("IEND", ""),
+        ]);
+        let include = [ChunkType::from_str("tRNS").unwrap()];
+
+        let mut other_palette = Png::from_chunks(vec![
+            indexed,
+            chunk("PLTE", "xyz"),
+            chunk("IDAT", "x"),
+            chunk("IEND", ""),
+        ]);
+        let mut other_type = Png::from_chunks(vec![rgb, chunk("IDAT", "x"), chunk("IEND", "")]);
+        for destination in [&mut other_palette, &mut other_type] {
+            let results = copy_metadata(&source, destination, &include, &[]);
+            assert_eq!(
+                actions(&results),
+                [("tRNS".to_string(), CopyAction::Incompatible)]
+            );
+        }
+
+        let mut same = Png::from_chunks(source.chunks()[..2].to_vec());
+        let results = copy_metadata(&source, &mut same, &include, &[]);
+        assert_eq!(
+            actions(&results),
+            [("tRNS".to_string(), CopyAction::Inserted)]
+        );
+    }
+
+    #[test]
+    fn test_copy_metadata_existing_chunks() {
+        let source = png(&[
+            ("IHDR", "h"),
+            ("pHYs", "new"),
+            ("IDAT", "d"),
+            ("tEXt", "same"),
+            ("tEXt", "other"),
+            ("IEND", ""),
+        ]);
+        let mut destination = png(&[
+            ("IHDR", "x"),
+            ("pHYs", "old"),
+            ("IDAT", "x"),
+            ("tEXt", "same"),
+            ("IEND", ""),
+        ]);
+
+        let results = copy_metadata(&source, &mut destination, &[], &[]);
+        assert_eq!(
+            actions(&results),
+            [
+                ("pHYs".to_string(), CopyAction::Replaced),
+                ("tEXt".to_string(), CopyAction::AlreadyPresent),
+                ("tEXt".to_string(), CopyAction::Inserted),
+            ]
+        );
+        assert_eq!(destination.chunks()[1].data(), b"new");
+        assert_eq!(
+            types(&destination),
+            ["IHDR", "pHYs", "IDAT", "tEXt", "tEXt", "IEND"]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use core::fmt;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Path standing for stdin when reading and stdout when writing
+pub const STDIO_PATH: &str = "-";
+
+#[derive(Debug)]
+pub enum OutputError {
+    InvalidPath(String),
+    Exists(String),
+    Unspecified(String),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputError::InvalidPath(e) => write!(f, "Output Error: {}", e),
+            OutputError::Exists(e) => write!(f, "Output Error: {}", e),
+            OutputError::Unspecified(e) => write!(f, "Output Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// Decides where a command that rewrites `input` writes its result. Output paths are used as
+/// given, stdin input goes to stdout by default, and overwriting needs --in-place or --force.
+pub fn destination(
+    input: &Path,
+    output: Option<PathBuf>,
+    in_place: bool,
+    force: bool,
+) -> Result<PathBuf> {
+    match output {
+        Some(output) if is_stdio(&output) => Ok(output),
+        Some(output) => {
+            if output.exists() && !force {
+                let error_message = format!(
+                    "{} already exists. Pass --force to overwrite it",
+                    output.display()
+                );
+                return Err(OutputError::Exists(error_message).into());
+            }
+            Ok(output)
+        }
+        None if is_stdio(input) && in_place => {
+            let error_message = "Cannot edit stdin in place".to_string();
+            Err(OutputError::InvalidPath(error_message).into())
+        }
+        None if is_stdio(input) || in_place => Ok(input.to_path_buf()),
+        None => {
+            let error_message = format!(
+                "Pass --output FILE, or --in-place to overwrite {}",
+                input.display()
+            );
+            Err(OutputError::Unspecified(error_message).into())
+        }
+    }
+}
+
+/// Replaces a file without ever leaving it half-written. The data goes to a temporary file in
+/// the same directory, which is synced and renamed over the target. An existing target keeps
+/// its permissions and timestamps, and is first linked or copied to `path` + `backup_suffix`
+/// when a suffix is given. A symlink is followed, so the file it points to is replaced and the
+/// link itself is kept.
+pub fn write_atomic(path: &Path, data: &[u8], backup_suffix: Option<&str>) -> Result<()> {
+    let resolved = resolve_symlink(path)?;
+    let path = resolved.as_deref().unwrap_or(path);
+
+    let file_name = path.file_name().ok_or_else(|| {
+        let error_message = format!("{} is not a file path", path.display());
+        OutputError::InvalidPath(error_message)
+    })?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let existing = fs::metadata(path).ok();
+
+    let mut temp_name = format!(".{}.", file_name.to_string_lossy());
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    temp_name.push_str(&format!("{}-{}.tmp", process::id(), nanos));
+    let temp_path = directory.join(temp_name);
+
+    let result = write_temp(&temp_path, data, existing.as_ref()).and_then(|()| {
+        if let (Some(suffix), Some(_)) = (backup_suffix, &existing) {
+            backup(path, suffix)?;
+        }
+        fs::rename(&temp_path, path)?;
+        sync_directory(&directory)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Returns the canonical target of a symlink, or None if the path is not one
+fn resolve_symlink(path: &Path) -> Result<Option<PathBuf>> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            let target = fs::canonicalize(path).map_err(|e| {
+                let error_message = format!("Cannot follow the symlink {}: {}", path.display(), e);
+                OutputError::InvalidPath(error_message)
+            })?;
+            Ok(Some(target))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn write_temp(temp_path: &Path, data: &[u8], existing: Option<&fs::Metadata>) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp_path)?;
+    file.write_all(data)?;
+
+    if let Some(metadata) = existing {
+        file.set_permissions(metadata.permissions())?;
+        let mut times = FileTimes::new();
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        if let Ok(modified) = metadata.modified() {
+            times = times.set_modified(modified);
+        }
+        file.set_times(times)?;
+    }
+
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Keeps the current file at `path` + `suffix`, replacing an older backup
+fn backup(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(suffix);
+    let backup_path = PathBuf::from(backup_path);
+
+    if backup_path == path {
+        let error_message = "Backup suffix must not be empty".to_string();
+        return Err(OutputError::InvalidPath(error_message).into());
+    }
+
+    match fs::remove_file(&backup_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    if fs::hard_link(path, &backup_path).is_err() {
+        fs::copy(path, &backup_path)?;
+    }
+
+    Ok(backup_path)
+}
+
+/// Makes the rename itself durable. Directories cannot be opened for syncing on Windows.
+fn sync_directory(directory: &Path) -> Result<()> {
+    if cfg!(unix) {
+        File::open(directory)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-output-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_destination() {
+        let dir = temp_dir("destination");
+        let input = dir.join("input.png");
+        let output = dir.join("output.png");
+        fs::write(&input, b"data").unwrap();
+
+        assert_eq!(
+            destination(&input, Some(output.clone()), false, false).unwrap(),
+            output
+        );
+        assert_eq!(destination(&input, None, true, false).unwrap(), input);
+        assert!(destination(&input, None, false, false).is_err());
+
+        let error = destination(&input, Some(input.clone()), false, false).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OutputError>(),
+            Some(OutputError::Exists(_))
+        ));
+        assert_eq!(
+            destination(&input, Some(input.clone()), false, true).unwrap(),
+            input
+        );
+
+        let stdio = PathBuf::from(STDIO_PATH);
+        assert_eq!(destination(&stdio, None, false, false).unwrap(), stdio);
+        assert!(destination(&stdio, None, true, false).is_err());
+        assert_eq!(
+            destination(&input, Some(stdio.clone()), false, false).unwrap(),
+            stdio
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_creates_file() {
+        let dir = temp_dir("create");
+        let path = dir.join("new.png");
+
+        write_atomic(&path, b"data", None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_metadata() {
+        let dir = temp_dir("metadata");
+        let path = dir.join("image.png");
+        fs::write(&path, b"old").unwrap();
+
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        let mut permissions = file.metadata().unwrap().permissions();
+        permissions.set_readonly(true);
+        file.set_permissions(permissions).unwrap();
+        drop(file);
+
+        write_atomic(&path, b"new", None).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(metadata.permissions().readonly());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_follows_symlink() {
+        let dir = temp_dir("symlink");
+        let target = dir.join("target.png");
+        let link = dir.join("link.png");
+        fs::write(&target, b"old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"new", None).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), target);
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+
+        let dangling = dir.join("dangling.png");
+        std::os::unix::fs::symlink(dir.join("missing.png"), &dangling).unwrap();
+        assert!(write_atomic(&dangling, b"new", None).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_backup() {
+        let dir = temp_dir("backup");
+        let path = dir.join("image.png");
+        fs::write(&path, b"first").unwrap();
+
+        write_atomic(&path, b"second", Some(".bak")).unwrap();
+        write_atomic(&path, b"third", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+        assert_eq!(fs::read(dir.join("image.png.bak")).unwrap(), b"second");
+        assert!(write_atomic(&path, b"fourth", Some("")).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
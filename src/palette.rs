@@ -0,0 +1,507 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::{ColorType, ImageHeader};
+use core::fmt;
+use serde_json::json;
+use std::str::FromStr;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+/// Typed contents of a PLTE chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub entries: Vec<Rgb>,
+}
+
+/// Typed contents of a tRNS chunk, whose layout depends on the image color type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    /// Alpha values for the leading palette entries; missing entries are fully opaque
+    Indexed(Vec<u8>),
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+/// Typed contents of a bKGD chunk, whose layout depends on the image color type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    PaletteIndex(u8),
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    InvalidChunk(String),
+    InvalidEntry(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::InvalidChunk(e) => write!(f, "Palette Error: {}", e),
+            PaletteError::InvalidEntry(e) => write!(f, "Palette Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl FromStr for Rgb {
+    type Err = Error;
+
+    /// Parses a color written as `rrggbb` or `#rrggbb`
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            let error_message = format!("Invalid color {}, expected #rrggbb", s);
+            return Err(PaletteError::InvalidEntry(error_message).into());
+        }
+
+        Ok(Rgb {
+            red: u8::from_str_radix(&hex[0..2], 16)?,
+            green: u8::from_str_radix(&hex[2..4], 16)?,
+            blue: u8::from_str_radix(&hex[4..6], 16)?,
+        })
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+impl Palette {
+    pub const CHUNK_TYPE: &'static str = "PLTE";
+
+    /// Parses a PLTE chunk and checks it against the image header
+    pub fn from_chunk(chunk: &Chunk, header: &ImageHeader) -> Result<Palette> {
+        expect_type(chunk, Self::CHUNK_TYPE)?;
+
+        let data = chunk.data();
+        if data.is_empty() || !data.len().is_multiple_of(3) {
+            let error_message = format!(
+                "PLTE length must be a non-zero multiple of 3, found {}",
+                data.len()
+            );
+            return Err(PaletteError::InvalidChunk(error_message).into());
+        }
+
+        let palette = Palette {
+            entries: data
+                .chunks_exact(3)
+                .map(|rgb| Rgb {
+                    red: rgb[0],
+                    green: rgb[1],
+                    blue: rgb[2],
+                })
+                .collect(),
+        };
+        palette.validate(header)?;
+
+        Ok(palette)
+    }
+
+    pub fn validate(&self, header: &ImageHeader) -> Result<()> {
+        let max_entries = match header.color_type {
+            ColorType::Indexed => 1usize << header.bit_depth,
+            ColorType::Rgb | ColorType::Rgba => 256,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                let error_message = format!("PLTE is not allowed for {} images", header.color_type);
+                return Err(PaletteError::InvalidChunk(error_message).into());
+            }
+        };
+
+        if self.entries.is_empty() || self.entries.len() > max_entries {
+            let error_message = format!(
+                "Palette has {} entries, but a {}-bit {} image allows 1 to {}",
+                self.entries.len(),
+                header.bit_depth,
+                header.color_type,
+                max_entries
+            );
+            return Err(PaletteError::InvalidChunk(error_message).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn set_entry(&mut self, index: usize, color: Rgb) -> Result<()> {
+        let entry = self.entries.get_mut(index).ok_or_else(|| {
+            let error_message = format!("Palette index {} is out of range", index);
+            PaletteError::InvalidEntry(error_message)
+        })?;
+        *entry = color;
+
+        Ok(())
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = self
+            .entries
+            .iter()
+            .flat_map(|rgb| [rgb.red, rgb.green, rgb.blue])
+            .collect();
+
+        Chunk::new(ChunkType::from_str(Self::CHUNK_TYPE).unwrap(), data)
+    }
+
+    /// Formats the palette as a GIMP palette file
+    pub fn to_gpl(&self, name: &str, transparency: Option<&Transparency>) -> String {
+        let mut result = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+        for (index, rgb) in self.entries.iter().enumerate() {
+            let alpha = transparency.map_or(255, |t| t.alpha_for_index(index));
+            result.push_str(&format!(
+                "{:3} {:3} {:3}\tIndex {} (alpha {})\n",
+                rgb.red, rgb.green, rgb.blue, index, alpha
+            ));
+        }
+        result
+    }
+
+    /// Formats the palette as a JSON document with one object per entry
+    pub fn to_json(
+        &self,
+        transparency: Option<&Transparency>,
+        background: Option<&Background>,
+    ) -> Result<String> {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, rgb)| {
+                json!({
+                    "index": index,
+                    "hex": rgb.to_string(),
+                    "red": rgb.red,
+                    "green": rgb.green,
+                    "blue": rgb.blue,
+                    "alpha": transparency.map_or(255, |t| t.alpha_for_index(index)),
+                })
+            })
+            .collect();
+
+        let document = json!({
+            "entries": entries,
+            "background": background.map(|b| b.to_string()),
+        });
+
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+}
+
+impl Transparency {
+    pub const CHUNK_TYPE: &'static str = "tRNS";
+
+    /// Parses a tRNS chunk and checks it against the image header and palette
+    pub fn from_chunk(
+        chunk: &Chunk,
+        header: &ImageHeader,
+        palette: Option<&Palette>,
+    ) -> Result<Transparency> {
+        expect_type(chunk, Self::CHUNK_TYPE)?;
+
+        let data = chunk.data();
+        let transparency = match header.color_type {
+            ColorType::Indexed => Transparency::Indexed(data.to_vec()),
+            ColorType::Grayscale if data.len() == 2 => {
+                Transparency::Gray(u16::from_be_bytes([data[0], data[1]]))
+            }
+            ColorType::Rgb if data.len() == 6 => Transparency::Rgb(
+                u16::from_be_bytes([data[0], data[1]]),
+                u16::from_be_bytes([data[2], data[3]]),
+                u16::from_be_bytes([data[4], data[5]]),
+            ),
+            _ => {
+                let error_message = format!(
+                    "tRNS of {} bytes is not valid for {} images",
+                    data.len(),
+                    header.color_type
+                );
+                return Err(PaletteError::InvalidChunk(error_message).into());
+            }
+        };
+        transparency.validate(header, palette)?;
+
+        Ok(transparency)
+    }
+
+    pub fn validate(&self, header: &ImageHeader, palette: Option<&Palette>) -> Result<()> {
+        let max = header.max_sample_value();
+        let valid = match (self, header.color_type) {
+            (Transparency::Indexed(alphas), ColorType::Indexed) => {
+                let entries = palette.map_or(0, |p| p.entries.len());
+                if alphas.len() > entries {
+                    let error_message = format!(
+                        "tRNS has {} entries but the palette only has {}",
+                        alphas.len(),
+                        entries
+                    );
+                    return Err(PaletteError::InvalidChunk(error_message).into());
+                }
+                true
+            }
+            (Transparency::Gray(gray), ColorType::Grayscale) => *gray <= max,
+            (Transparency::Rgb(r, g, b), ColorType::Rgb) => *r <= max && *g <= max && *b <= max,
+            _ => false,
+        };
+
+        valid.then_some(()).ok_or_else(|| {
+            let error_message = format!(
+                "tRNS does not match a {}-bit {} image",
+                header.bit_depth, header.color_type
+            );
+            PaletteError::InvalidChunk(error_message).into()
+        })
+    }
+
+    /// Returns the alpha value for a palette index, treating missing entries as opaque
+    pub fn alpha_for_index(&self, index: usize) -> u8 {
+        match self {
+            Transparency::Indexed(alphas) => alphas.get(index).copied().unwrap_or(255),
+            _ => 255,
+        }
+    }
+
+    /// Sets the alpha value for a palette index, extending the table with opaque entries
+    pub fn set_alpha(&mut self, index: usize, alpha: u8) -> Result<()> {
+        match self {
+            Transparency::Indexed(alphas) => {
+                if alphas.len() <= index {
+                    alphas.resize(index + 1, 255);
+                }
+                alphas[index] = alpha;
+                Ok(())
+            }
+            _ => {
+                let error_message = "Per-entry alpha requires an indexed-color image".to_string();
+                Err(PaletteError::InvalidEntry(error_message).into())
+            }
+        }
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = match self {
+            Transparency::Indexed(alphas) => alphas.clone(),
+            Transparency::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Transparency::Rgb(r, g, b) => {
+                [*r, *g, *b].iter().flat_map(|v| v.to_be_bytes()).collect()
+            }
+        };
+
+        Chunk::new(ChunkType::from_str(Self::CHUNK_TYPE).unwrap(), data)
+    }
+}
+
+impl Background {
+    pub const CHUNK_TYPE: &'static str = "bKGD";
+
+    /// Parses a bKGD chunk and checks it against the image header and palette
+    pub fn from_chunk(
+        chunk: &Chunk,
+        header: &ImageHeader,
+        palette: Option<&Palette>,
+    ) -> Result<Background> {
+        expect_type(chunk, Self::CHUNK_TYPE)?;
+
+        let data = chunk.data();
+        let max = header.max_sample_value();
+        let background = match (header.color_type, data.len()) {
+            (ColorType::Indexed, 1) => {
+                let entries = palette.map_or(0, |p| p.entries.len());
+                (usize::from(data[0]) < entries).then_some(Background::PaletteIndex(data[0]))
+            }
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, 2) => {
+                let gray = u16::from_be_bytes([data[0], data[1]]);
+                (gray <= max).then_some(Background::Gray(gray))
+            }
+            (ColorType::Rgb | ColorType::Rgba, 6) => {
+                let r = u16::from_be_bytes([data[0], data[1]]);
+                let g = u16::from_be_bytes([data[2], data[3]]);
+                let b = u16::from_be_bytes([data[4], data[5]]);
+                (r <= max && g <= max && b <= max).then_some(Background::Rgb(r, g, b))
+            }
+            _ => None,
+        };
+
+        background.ok_or_else(|| {
+            let error_message = format!(
+                "bKGD does not match a {}-bit {} image",
+                header.bit_depth, header.color_type
+            );
+            PaletteError::InvalidChunk(error_message).into()
+        })
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::PaletteIndex(index) => write!(f, "palette index {}", index),
+            Background::Gray(gray) => write!(f, "gray {}", gray),
+            Background::Rgb(r, g, b) => write!(f, "rgb({}, {}, {})", r, g, b),
+        }
+    }
+}
+
+fn expect_type(chunk: &Chunk, chunk_type: &str) -> Result<()> {
+    if chunk.chunk_type().to_string() != chunk_type {
+        let error_message = format!(
+            "Expected a {} chunk, found {}",
+            chunk_type,
+            chunk.chunk_type()
+        );
+        return Err(PaletteError::InvalidChunk(error_message).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_header(color_type: ColorType, bit_depth: u8) -> ImageHeader {
+        ImageHeader {
+            width: 4,
+            height: 4,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        }
+    }
+
+    fn indexed_header(bit_depth: u8) -> ImageHeader {
+        testing_header(ColorType::Indexed, bit_depth)
+    }
+
+    fn plte_chunk(entries: usize) -> Chunk {
+        let data = (0..entries * 3).map(|i| i as u8).collect();
+        Chunk::new(ChunkType::from_str("PLTE").unwrap(), data)
+    }
+
+    #[test]
+    fn test_palette_from_chunk() {
+        let palette = Palette::from_chunk(&plte_chunk(4), &indexed_header(2)).unwrap();
+        assert_eq!(palette.entries.len(), 4);
+        assert_eq!(
+            palette.entries[1],
+            Rgb {
+                red: 3,
+                green: 4,
+                blue: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_palette_too_large_for_bit_depth() {
+        assert!(Palette::from_chunk(&plte_chunk(5), &indexed_header(2)).is_err());
+    }
+
+    #[test]
+    fn test_palette_not_allowed_for_grayscale() {
+        let header = testing_header(ColorType::Grayscale, 8);
+        assert!(Palette::from_chunk(&plte_chunk(4), &header).is_err());
+    }
+
+    #[test]
+    fn test_palette_invalid_length() {
+        let chunk = Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![1, 2, 3, 4]);
+        assert!(Palette::from_chunk(&chunk, &indexed_header(8)).is_err());
+    }
+
+    #[test]
+    fn test_palette_set_entry() {
+        let mut palette = Palette::from_chunk(&plte_chunk(4), &indexed_header(8)).unwrap();
+        let color = Rgb::from_str("#ff8000").unwrap();
+        palette.set_entry(2, color).unwrap();
+        assert_eq!(palette.to_chunk().data()[6..9], [255, 128, 0]);
+        assert!(palette.set_entry(4, color).is_err());
+    }
+
+    #[test]
+    fn test_rgb_from_str() {
+        assert_eq!(Rgb::from_str("00ff10").unwrap().to_string(), "#00ff10");
+        assert!(Rgb::from_str("#fff").is_err());
+        assert!(Rgb::from_str("zzzzzz").is_err());
+    }
+
+    #[test]
+    fn test_transparency_indexed() {
+        let header = indexed_header(8);
+        let palette = Palette::from_chunk(&plte_chunk(4), &header).unwrap();
+
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0, 128]);
+        let mut trns = Transparency::from_chunk(&chunk, &header, Some(&palette)).unwrap();
+        assert_eq!(trns.alpha_for_index(1), 128);
+        assert_eq!(trns.alpha_for_index(3), 255);
+
+        trns.set_alpha(3, 10).unwrap();
+        assert_eq!(trns.to_chunk().data(), &[0, 128, 255, 10]);
+
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0; 5]);
+        assert!(Transparency::from_chunk(&chunk, &header, Some(&palette)).is_err());
+    }
+
+    #[test]
+    fn test_transparency_gray_exceeds_bit_depth() {
+        let header = testing_header(ColorType::Grayscale, 4);
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0, 15]);
+        assert!(Transparency::from_chunk(&chunk, &header, None).is_ok());
+
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0, 16]);
+        assert!(Transparency::from_chunk(&chunk, &header, None).is_err());
+    }
+
+    #[test]
+    fn test_transparency_not_allowed_with_alpha_channel() {
+        let header = testing_header(ColorType::Rgba, 8);
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0; 6]);
+        assert!(Transparency::from_chunk(&chunk, &header, None).is_err());
+    }
+
+    #[test]
+    fn test_background() {
+        let header = indexed_header(8);
+        let palette = Palette::from_chunk(&plte_chunk(4), &header).unwrap();
+
+        let chunk = Chunk::new(ChunkType::from_str("bKGD").unwrap(), vec![3]);
+        let background = Background::from_chunk(&chunk, &header, Some(&palette)).unwrap();
+        assert_eq!(background, Background::PaletteIndex(3));
+
+        let chunk = Chunk::new(ChunkType::from_str("bKGD").unwrap(), vec![4]);
+        assert!(Background::from_chunk(&chunk, &header, Some(&palette)).is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let palette = Palette::from_chunk(&plte_chunk(2), &indexed_header(1)).unwrap();
+        let trns = Transparency::Indexed(vec![7]);
+        let json = palette.to_json(Some(&trns), None).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["entries"][0]["alpha"], 7);
+        assert_eq!(value["entries"][1]["hex"], "#030405");
+        assert!(value["background"].is_null());
+    }
+
+    #[test]
+    fn test_to_gpl() {
+        let palette = Palette::from_chunk(&plte_chunk(2), &indexed_header(1)).unwrap();
+        let trns = Transparency::Indexed(vec![0]);
+        let gpl = palette.to_gpl("test", Some(&trns));
+        assert!(gpl.starts_with("GIMP Palette\nName: test\n"));
+        assert!(gpl.contains("  0   1   2\tIndex 0 (alpha 0)"));
+        assert!(gpl.contains("  3   4   5\tIndex 1 (alpha 255)"));
+    }
+}
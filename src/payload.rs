@@ -0,0 +1,632 @@
+use core::fmt;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Compressed payloads start with this magic, the algorithm and the original length as u32 BE
+const COMPRESSED_MAGIC: [u8; 4] = *b"PMCZ";
+const COMPRESSED_HEADER_LEN: usize = COMPRESSED_MAGIC.len() + 1 + 4;
+
+/// File payloads start with this magic, the name (u16 BE length) and the MIME type (u8 length)
+const FILE_MAGIC: [u8; 4] = *b"PMFL";
+
+/// Split payloads store each piece after this magic, the piece index and piece count as u32 BE
+/// and the SHA-256 of the whole payload
+const SEQUENCE_MAGIC: [u8; 4] = *b"PMSQ";
+const SEQUENCE_HEADER_LEN: usize = SEQUENCE_MAGIC.len() + 8 + 32;
+
+/// Every encoded payload starts with this magic, the format version and the layer flags. The
+/// first byte cannot start a UTF-8 string, so plain text messages from before frames existed
+/// are still told apart.
+const FRAME_MAGIC: [u8; 4] = [0x89, b'P', b'M', b'F'];
+const FRAME_VERSION: u8 = 1;
+const FRAME_HEADER_LEN: usize = FRAME_MAGIC.len() + 2;
+
+const FLAG_TAGGED: u8 = 0x01;
+const FLAG_ENCRYPTED: u8 = 0x02;
+const FLAG_COMPRESSED: u8 = 0x04;
+const FLAG_FILE: u8 = 0x08;
+const FLAG_SEQUENCED: u8 = 0x10;
+
+/// MIME types for common file extensions, anything else is stored as application/octet-stream
+const MIME_TYPES: [(&str, &str); 16] = [
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Deflate,
+    Zstd,
+}
+
+/// Name and MIME type of a file stored as the message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub name: String,
+    pub mime_type: String,
+}
+
+/// The layers encode wrapped the message in, innermost first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Layers {
+    pub file: bool,
+    pub compressed: bool,
+    pub encrypted: bool,
+    pub tagged: bool,
+}
+
+/// The frame header of one stored payload and the bytes after it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub layers: Layers,
+    pub sequenced: bool,
+    pub body: &'a [u8],
+}
+
+#[derive(Debug)]
+pub enum PayloadError {
+    InvalidHeader(String),
+    Compression(String),
+    Binary(String),
+    InvalidSource(String),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadError::InvalidHeader(e) => write!(f, "Payload Error: {}", e),
+            PayloadError::Compression(e) => write!(f, "Payload Error: {}", e),
+            PayloadError::Binary(e) => write!(f, "Payload Error: {}", e),
+            PayloadError::InvalidSource(e) => write!(f, "Payload Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+impl Algorithm {
+    fn from_byte(byte: u8) -> Result<Algorithm> {
+        match byte {
+            1 => Ok(Algorithm::Deflate),
+            2 => Ok(Algorithm::Zstd),
+            _ => {
+                let error_message = format!("Unknown compression algorithm {}", byte);
+                Err(PayloadError::InvalidHeader(error_message).into())
+            }
+        }
+    }
+
+    fn as_byte(&self) -> u8 {
+        match self {
+            Algorithm::Deflate => 1,
+            Algorithm::Zstd => 2,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Algorithm::Deflate => write!(f, "deflate"),
+            Algorithm::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl Layers {
+    /// The frame header byte recording these layers
+    pub fn flags(&self) -> u8 {
+        [
+            (self.tagged, FLAG_TAGGED),
+            (self.encrypted, FLAG_ENCRYPTED),
+            (self.compressed, FLAG_COMPRESSED),
+            (self.file, FLAG_FILE),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, flag)| flags | flag)
+    }
+
+    fn from_flags(flags: u8) -> Layers {
+        Layers {
+            file: flags & FLAG_FILE != 0,
+            compressed: flags & FLAG_COMPRESSED != 0,
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+            tagged: flags & FLAG_TAGGED != 0,
+        }
+    }
+}
+
+impl FileInfo {
+    /// Uses the file name without its directories and guesses the MIME type from the extension
+    pub fn for_path(path: &Path) -> Result<FileInfo> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                let error_message = format!("{} has no UTF-8 file name", path.display());
+                PayloadError::InvalidHeader(error_message)
+            })?;
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mime_type = MIME_TYPES
+            .iter()
+            .find(|(known, _)| *known == extension)
+            .map_or("application/octet-stream", |(_, mime_type)| *mime_type);
+
+        Ok(FileInfo {
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.mime_type)
+    }
+}
+
+/// Prefixes the payload with a frame header recording its layers. A plain message has no
+/// layers to record and is returned as is, which `unframe` reads back the same way.
+pub fn frame(layers: Layers, payload: &[u8]) -> Vec<u8> {
+    if layers == Layers::default() {
+        return payload.to_vec();
+    }
+    [&FRAME_MAGIC[..], &[FRAME_VERSION, layers.flags()], payload].concat()
+}
+
+/// Like `frame`, but splits the payload into sequenced pieces when the frame would be larger
+/// than `max_len`
+pub fn frame_pieces(layers: Layers, payload: &[u8], max_len: usize) -> Result<Vec<Vec<u8>>> {
+    let header_len = if layers == Layers::default() {
+        0
+    } else {
+        FRAME_HEADER_LEN
+    };
+    if header_len + payload.len() <= max_len {
+        return Ok(vec![frame(layers, payload)]);
+    }
+    if max_len <= FRAME_HEADER_LEN + SEQUENCE_HEADER_LEN {
+        let error_message = format!(
+            "Chunk size must be larger than the {} bytes of frame and sequence headers",
+            FRAME_HEADER_LEN + SEQUENCE_HEADER_LEN
+        );
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    }
+
+    let flags = layers.flags() | FLAG_SEQUENCED;
+    Ok(split(payload, max_len - FRAME_HEADER_LEN)?
+        .iter()
+        .map(|piece| [&FRAME_MAGIC[..], &[FRAME_VERSION, flags], piece].concat())
+        .collect())
+}
+
+/// Reads the frame header written by `frame` or `frame_pieces`. Data without one is a plain
+/// message stored before frames existed and comes back with no layers.
+pub fn unframe(data: &[u8]) -> Result<Frame<'_>> {
+    let Some(rest) = data.strip_prefix(&FRAME_MAGIC) else {
+        return Ok(Frame {
+            layers: Layers::default(),
+            sequenced: false,
+            body: data,
+        });
+    };
+
+    let (Some(&version), Some(&flags)) = (rest.first(), rest.get(1)) else {
+        let error_message = "Frame header is truncated".to_string();
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    };
+    if version != FRAME_VERSION {
+        let error_message = format!("Unsupported payload format version {}", version);
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    }
+    let known = FLAG_TAGGED | FLAG_ENCRYPTED | FLAG_COMPRESSED | FLAG_FILE | FLAG_SEQUENCED;
+    if flags & !known != 0 {
+        let error_message = format!("Unknown payload flags {:#04x}", flags);
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    }
+
+    Ok(Frame {
+        layers: Layers::from_flags(flags),
+        sequenced: flags & FLAG_SEQUENCED != 0,
+        body: &rest[2..],
+    })
+}
+
+/// Returns true if the data starts with the header written by `wrap_file`
+fn is_file(data: &[u8]) -> bool {
+    data.starts_with(&FILE_MAGIC)
+}
+
+/// Prefixes raw file contents with a header holding the file name and MIME type
+pub fn wrap_file(info: &FileInfo, data: &[u8]) -> Result<Vec<u8>> {
+    let name_len = u16::try_from(info.name.len());
+    let mime_len = u8::try_from(info.mime_type.len());
+    let (Ok(name_len), Ok(mime_len)) = (name_len, mime_len) else {
+        let error_message = format!("File name or MIME type of {} is too long", info);
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    };
+
+    let mut wrapped = FILE_MAGIC.to_vec();
+    wrapped.extend_from_slice(&name_len.to_be_bytes());
+    wrapped.extend_from_slice(info.name.as_bytes());
+    wrapped.push(mime_len);
+    wrapped.extend_from_slice(info.mime_type.as_bytes());
+    wrapped.extend_from_slice(data);
+
+    Ok(wrapped)
+}
+
+/// Reverses `wrap_file`
+pub fn unwrap_file(data: &[u8]) -> Result<(FileInfo, Vec<u8>)> {
+    let invalid = || -> Error {
+        let error_message = "File header is malformed".to_string();
+        PayloadError::InvalidHeader(error_message).into()
+    };
+    if !is_file(data) {
+        return Err(invalid());
+    }
+
+    let rest = &data[FILE_MAGIC.len()..];
+    let name_len = u16::from_be_bytes(rest.get(..2).ok_or_else(invalid)?.try_into()?) as usize;
+    let name = rest.get(2..2 + name_len).ok_or_else(invalid)?;
+    let rest = &rest[2 + name_len..];
+
+    let mime_len = *rest.first().ok_or_else(invalid)? as usize;
+    let mime_type = rest.get(1..1 + mime_len).ok_or_else(invalid)?;
+    let contents = &rest[1 + mime_len..];
+
+    let info = FileInfo {
+        name: String::from_utf8(name.to_vec())?,
+        mime_type: String::from_utf8(mime_type.to_vec())?,
+    };
+
+    Ok((info, contents.to_vec()))
+}
+
+/// Returns true if the data is one piece written by `split`
+fn is_sequenced(data: &[u8]) -> bool {
+    data.starts_with(&SEQUENCE_MAGIC)
+}
+
+/// Splits a payload larger than `max_len` into numbered pieces of at most `max_len` bytes
+/// each, headers included. Smaller payloads are returned unchanged as a single piece.
+pub fn split(payload: &[u8], max_len: usize) -> Result<Vec<Vec<u8>>> {
+    if payload.len() <= max_len {
+        return Ok(vec![payload.to_vec()]);
+    }
+    if max_len <= SEQUENCE_HEADER_LEN {
+        let error_message = format!(
+            "Chunk size must be larger than the {} byte sequence header",
+            SEQUENCE_HEADER_LEN
+        );
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    }
+
+    let digest: [u8; 32] = Sha256::digest(payload).into();
+    let pieces: Vec<&[u8]> = payload.chunks(max_len - SEQUENCE_HEADER_LEN).collect();
+    let total = u32::try_from(pieces.len())?;
+
+    Ok(pieces
+        .iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            let mut data = SEQUENCE_MAGIC.to_vec();
+            data.extend_from_slice(&(index as u32).to_be_bytes());
+            data.extend_from_slice(&total.to_be_bytes());
+            data.extend_from_slice(&digest);
+            data.extend_from_slice(piece);
+            data
+        })
+        .collect())
+}
+
+/// Puts the pieces of the first split payload back in order and checks its hash. Pieces
+/// belonging to other payloads, identified by a different hash, are ignored.
+pub fn reassemble(pieces: &[&[u8]]) -> Result<Vec<u8>> {
+    let invalid =
+        |error_message: String| -> Error { PayloadError::InvalidHeader(error_message).into() };
+
+    let mut parsed = Vec::new();
+    for piece in pieces {
+        if !is_sequenced(piece) || piece.len() < SEQUENCE_HEADER_LEN {
+            return Err(invalid("Sequence header is malformed".to_string()));
+        }
+        let index = u32::from_be_bytes(piece[4..8].try_into().unwrap());
+        let total = u32::from_be_bytes(piece[8..12].try_into().unwrap());
+        let digest = &piece[12..SEQUENCE_HEADER_LEN];
+        parsed.push((index, total, digest, &piece[SEQUENCE_HEADER_LEN..]));
+    }
+
+    let Some((_, total, digest, _)) = parsed.first().copied() else {
+        return Err(invalid("No pieces to reassemble".to_string()));
+    };
+    parsed.retain(|(_, _, piece_digest, _)| *piece_digest == digest);
+    parsed.sort_by_key(|(index, _, _, _)| *index);
+
+    if parsed.len() != total as usize {
+        let error_message = format!(
+            "Expected {} pieces of the payload, found {}",
+            total,
+            parsed.len()
+        );
+        return Err(invalid(error_message));
+    }
+    let in_order = parsed
+        .iter()
+        .enumerate()
+        .all(|(position, (index, _, _, _))| *index as usize == position);
+    if !in_order {
+        let error_message = "Payload pieces are duplicated or out of range".to_string();
+        return Err(invalid(error_message));
+    }
+
+    let payload: Vec<u8> = parsed
+        .iter()
+        .flat_map(|(_, _, _, data)| data.iter().copied())
+        .collect();
+    if Sha256::digest(&payload).as_slice() != digest {
+        let error_message = "Reassembled payload does not match its hash".to_string();
+        return Err(invalid(error_message));
+    }
+
+    Ok(payload)
+}
+
+/// Returns true if the data starts with the header written by `compress`
+fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&COMPRESSED_MAGIC)
+}
+
+/// Compresses the data and prefixes it with a header recording the algorithm
+pub fn compress(algorithm: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+    let original_len = u32::try_from(data.len()).map_err(|_| {
+        let error_message = format!("{} bytes is too large to compress", data.len());
+        PayloadError::Compression(error_message)
+    })?;
+
+    let mut compressed = COMPRESSED_MAGIC.to_vec();
+    compressed.push(algorithm.as_byte());
+    compressed.extend_from_slice(&original_len.to_be_bytes());
+
+    match algorithm {
+        Algorithm::Deflate => {
+            let mut encoder = DeflateEncoder::new(compressed, Compression::best());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        Algorithm::Zstd => {
+            compressed.extend(zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)?);
+            Ok(compressed)
+        }
+    }
+}
+
+/// Reverses `compress`, refusing to inflate past the recorded original length
+pub fn decompress(data: &[u8]) -> Result<(Algorithm, Vec<u8>)> {
+    if !is_compressed(data) || data.len() < COMPRESSED_HEADER_LEN {
+        let error_message = "Payload is not compressed".to_string();
+        return Err(PayloadError::InvalidHeader(error_message).into());
+    }
+
+    let algorithm = Algorithm::from_byte(data[COMPRESSED_MAGIC.len()])?;
+    let original_len = u32::from_be_bytes(data[5..COMPRESSED_HEADER_LEN].try_into().unwrap());
+    let compressed = &data[COMPRESSED_HEADER_LEN..];
+
+    let reader: Box<dyn Read> = match algorithm {
+        Algorithm::Deflate => Box::new(DeflateDecoder::new(compressed)),
+        Algorithm::Zstd => Box::new(zstd::Decoder::new(compressed)?),
+    };
+
+    let mut decompressed = Vec::new();
+    reader
+        .take(original_len as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| PayloadError::Compression(e.to_string()))?;
+
+    if decompressed.len() != original_len as usize {
+        let error_message = format!(
+            "Expected {} bytes after decompression, found {}",
+            original_len,
+            decompressed.len()
+        );
+        return Err(PayloadError::Compression(error_message).into());
+    }
+
+    Ok((algorithm, decompressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_message() -> Vec<u8> {
+        (0..200)
+            .map(|i| format!("{{\"id\": {}, \"name\": \"item\"}}", i))
+            .collect::<Vec<_>>()
+            .join(",")
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = json_message();
+        for algorithm in [Algorithm::Deflate, Algorithm::Zstd] {
+            let compressed = compress(algorithm, &message).unwrap();
+            assert!(is_compressed(&compressed));
+            assert!(compressed.len() < message.len() / 4);
+            assert_eq!(
+                decompress(&compressed).unwrap(),
+                (algorithm, message.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_round_trip() {
+        let compressed = compress(Algorithm::Zstd, b"").unwrap();
+        assert_eq!(decompress(&compressed).unwrap().1, b"");
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let info = FileInfo::for_path(Path::new("some/dir/Report.PDF")).unwrap();
+        assert_eq!(info.name, "Report.PDF");
+        assert_eq!(info.mime_type, "application/pdf");
+
+        let contents = vec![0, 159, 146, 150, 255];
+        let wrapped = wrap_file(&info, &contents).unwrap();
+        assert!(is_file(&wrapped));
+        assert_eq!(unwrap_file(&wrapped).unwrap(), (info, contents));
+    }
+
+    #[test]
+    fn test_unknown_mime_type() {
+        let info = FileInfo::for_path(Path::new("payload.bin")).unwrap();
+        assert_eq!(info.mime_type, "application/octet-stream");
+    }
+
+    #[test]
+    fn test_malformed_file_header() {
+        assert!(unwrap_file(b"PMFL").is_err());
+        assert!(unwrap_file(b"PMFL\x00\x09short").is_err());
+        assert!(unwrap_file(b"message").is_err());
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let payload = json_message();
+        assert_eq!(
+            split(&payload, payload.len()).unwrap(),
+            vec![payload.clone()]
+        );
+
+        let pieces = split(&payload, 1000).unwrap();
+        assert!(pieces.len() > 1);
+        assert!(
+            pieces
+                .iter()
+                .all(|piece| piece.len() <= 1000 && is_sequenced(piece))
+        );
+
+        let mut shuffled: Vec<&[u8]> = pieces.iter().map(|piece| piece.as_slice()).collect();
+        shuffled.reverse();
+        shuffled.rotate_left(1);
+        assert_eq!(reassemble(&shuffled).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_reassemble_ignores_other_payloads() {
+        let first = split(&json_message(), 500).unwrap();
+        let second = split(&[7; 2000], 500).unwrap();
+        let mut pieces: Vec<&[u8]> = first.iter().map(|piece| piece.as_slice()).collect();
+        pieces.insert(1, &second[0]);
+
+        assert_eq!(reassemble(&pieces).unwrap(), json_message());
+    }
+
+    #[test]
+    fn test_reassemble_detects_missing_and_altered_pieces() {
+        let pieces = split(&json_message(), 500).unwrap();
+        let mut slices: Vec<&[u8]> = pieces.iter().map(|piece| piece.as_slice()).collect();
+        slices.remove(2);
+        assert!(reassemble(&slices).is_err());
+
+        let mut duplicated: Vec<&[u8]> = pieces.iter().map(|piece| piece.as_slice()).collect();
+        duplicated[1] = duplicated[0];
+        assert!(reassemble(&duplicated).is_err());
+
+        let mut huge = pieces[0].clone();
+        huge[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(reassemble(&[&huge]).is_err());
+
+        let mut altered = pieces.clone();
+        *altered[1].last_mut().unwrap() ^= 1;
+        let slices: Vec<&[u8]> = altered.iter().map(|piece| piece.as_slice()).collect();
+        assert!(reassemble(&slices).is_err());
+
+        assert!(split(&json_message(), SEQUENCE_HEADER_LEN).is_err());
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let layers = Layers {
+            compressed: true,
+            tagged: true,
+            ..Layers::default()
+        };
+        let framed = frame(layers, b"PMEV hello");
+        assert_eq!(
+            unframe(&framed).unwrap(),
+            Frame {
+                layers,
+                sequenced: false,
+                body: b"PMEV hello",
+            }
+        );
+
+        let plain = unframe(b"PMCZ hi").unwrap();
+        assert_eq!(plain.layers, Layers::default());
+        assert_eq!(plain.body, b"PMCZ hi");
+        assert_eq!(frame(Layers::default(), b"hi"), b"hi");
+
+        assert!(unframe(&[0x89, b'P', b'M', b'F', 2, 0]).is_err());
+        assert!(unframe(&[0x89, b'P', b'M', b'F', 1, 0x80]).is_err());
+        assert!(unframe(&[0x89, b'P', b'M', b'F']).is_err());
+    }
+
+    #[test]
+    fn test_frame_pieces() {
+        let payload = json_message();
+        let pieces = frame_pieces(Layers::default(), &payload, 1000).unwrap();
+        assert!(pieces.len() > 1);
+
+        let frames: Vec<Frame> = pieces.iter().map(|piece| unframe(piece).unwrap()).collect();
+        assert!(frames.iter().all(|frame| frame.sequenced));
+        let bodies: Vec<&[u8]> = frames.iter().map(|frame| frame.body).collect();
+        assert_eq!(reassemble(&bodies).unwrap(), payload);
+
+        assert_eq!(
+            frame_pieces(Layers::default(), b"hi", 1000).unwrap(),
+            vec![b"hi".to_vec()]
+        );
+        assert!(frame_pieces(Layers::default(), &payload, 50).is_err());
+    }
+
+    #[test]
+    fn test_decompress_checks_length() {
+        let mut compressed = compress(Algorithm::Deflate, &json_message()).unwrap();
+        compressed[8] ^= 1;
+        assert!(decompress(&compressed).is_err());
+
+        compressed[4] = 9;
+        assert!(decompress(&compressed).is_err());
+        assert!(decompress(b"plain").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use core::fmt;
+use std::str::FromStr;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Meters per inch, used to convert between DPI and pixels per meter
+const METERS_PER_INCH: f64 = 0.0254;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Unknown,
+    Meter,
+}
+
+/// Typed contents of a pHYs chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit: Unit,
+}
+
+#[derive(Debug)]
+pub enum PhysError {
+    InvalidChunk(String),
+    InvalidDpi(String),
+}
+
+impl fmt::Display for PhysError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhysError::InvalidChunk(e) => write!(f, "pHYs Error: {}", e),
+            PhysError::InvalidDpi(e) => write!(f, "pHYs Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PhysError {}
+
+impl PhysicalDimensions {
+    pub const CHUNK_TYPE: &'static str = "pHYs";
+
+    /// Creates dimensions in pixels per meter from horizontal and vertical DPI values
+    pub fn from_dpi(dpi_x: f64, dpi_y: f64) -> Result<PhysicalDimensions> {
+        Ok(PhysicalDimensions {
+            pixels_per_unit_x: dpi_to_pixels_per_meter(dpi_x)?,
+            pixels_per_unit_y: dpi_to_pixels_per_meter(dpi_y)?,
+            unit: Unit::Meter,
+        })
+    }
+
+    /// Returns the horizontal and vertical DPI, or `None` if the unit is unknown
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            Unit::Meter => Some((
+                pixels_per_meter_to_dpi(self.pixels_per_unit_x),
+                pixels_per_meter_to_dpi(self.pixels_per_unit_y),
+            )),
+            Unit::Unknown => None,
+        }
+    }
+
+    pub fn to_chunk(self) -> Chunk {
+        let mut data = Vec::with_capacity(9);
+        data.extend_from_slice(&self.pixels_per_unit_x.to_be_bytes());
+        data.extend_from_slice(&self.pixels_per_unit_y.to_be_bytes());
+        data.push(match self.unit {
+            Unit::Unknown => 0,
+            Unit::Meter => 1,
+        });
+
+        Chunk::new(ChunkType::from_str(Self::CHUNK_TYPE).unwrap(), data)
+    }
+}
+
+impl TryFrom<&Chunk> for PhysicalDimensions {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != Self::CHUNK_TYPE {
+            let error_message = format!("Expected a pHYs chunk, found {}", chunk.chunk_type());
+            return Err(PhysError::InvalidChunk(error_message).into());
+        }
+
+        let data = chunk.data();
+        if data.len() != 9 {
+            let error_message = format!("pHYs data must be 9 bytes, found {}", data.len());
+            return Err(PhysError::InvalidChunk(error_message).into());
+        }
+
+        let unit = match data[8] {
+            0 => Unit::Unknown,
+            1 => Unit::Meter,
+            other => {
+                let error_message = format!("Unknown unit specifier {}", other);
+                return Err(PhysError::InvalidChunk(error_message).into());
+            }
+        };
+
+        Ok(PhysicalDimensions {
+            pixels_per_unit_x: u32::from_be_bytes(data[0..4].try_into()?),
+            pixels_per_unit_y: u32::from_be_bytes(data[4..8].try_into()?),
+            unit,
+        })
+    }
+}
+
+impl fmt::Display for PhysicalDimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dpi() {
+            Some((dpi_x, dpi_y)) => write!(
+                f,
+                "{} x {} pixels per meter ({:.2} x {:.2} DPI)",
+                self.pixels_per_unit_x, self.pixels_per_unit_y, dpi_x, dpi_y
+            ),
+            None => write!(
+                f,
+                "{} x {} pixels per unit (unit unknown, aspect ratio only)",
+                self.pixels_per_unit_x, self.pixels_per_unit_y
+            ),
+        }
+    }
+}
+
+pub fn dpi_to_pixels_per_meter(dpi: f64) -> Result<u32> {
+    let pixels_per_meter = (dpi / METERS_PER_INCH).round();
+
+    if !pixels_per_meter.is_finite() || pixels_per_meter < 1.0 || pixels_per_meter > u32::MAX as f64
+    {
+        let error_message = format!("DPI value {} is out of range", dpi);
+        return Err(PhysError::InvalidDpi(error_message).into());
+    }
+
+    Ok(pixels_per_meter as u32)
+}
+
+pub fn pixels_per_meter_to_dpi(pixels_per_meter: u32) -> f64 {
+    pixels_per_meter as f64 * METERS_PER_INCH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dpi_to_pixels_per_meter() {
+        assert_eq!(dpi_to_pixels_per_meter(72.0).unwrap(), 2835);
+        assert_eq!(dpi_to_pixels_per_meter(300.0).unwrap(), 11811);
+    }
+
+    #[test]
+    fn test_invalid_dpi() {
+        assert!(dpi_to_pixels_per_meter(0.0).is_err());
+        assert!(dpi_to_pixels_per_meter(-300.0).is_err());
+        assert!(dpi_to_pixels_per_meter(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_pixels_per_meter_to_dpi() {
+        let dpi = pixels_per_meter_to_dpi(11811);
+        assert!((dpi - 300.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_phys_round_trip() {
+        let phys = PhysicalDimensions::from_dpi(300.0, 150.0).unwrap();
+        let chunk = phys.to_chunk();
+        assert_eq!(chunk.length(), 9);
+        assert_eq!(&chunk.chunk_type().to_string(), "pHYs");
+
+        let actual = PhysicalDimensions::try_from(&chunk).unwrap();
+        assert_eq!(actual, phys);
+    }
+
+    #[test]
+    fn test_phys_unknown_unit_has_no_dpi() {
+        let phys = PhysicalDimensions {
+            pixels_per_unit_x: 1,
+            pixels_per_unit_y: 2,
+            unit: Unit::Unknown,
+        };
+        assert!(phys.dpi().is_none());
+    }
+
+    #[test]
+    fn test_phys_from_invalid_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("pHYs").unwrap(), vec![0; 8]);
+        assert!(PhysicalDimensions::try_from(&chunk).is_err());
+
+        let chunk = Chunk::new(
+            ChunkType::from_str("pHYs").unwrap(),
+            vec![0, 0, 0, 1, 0, 0, 0, 1, 2],
+        );
+        assert!(PhysicalDimensions::try_from(&chunk).is_err());
+
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0; 9]);
+        assert!(PhysicalDimensions::try_from(&chunk).is_err());
+    }
+}
@@ -1,6 +1,10 @@
 pub use crate::chunk::Chunk;
 use crate::chunk::ChunkError;
 pub use crate::chunk_type::ChunkType;
+use crate::decoder::{DecodedImage, RawImage};
+use crate::ihdr::{HeaderError, ImageHeader};
+use crate::image_data::ImageData;
+use crate::palette::{Palette, Transparency};
 use core::fmt;
 use std::convert::TryFrom;
 use std::io::{Cursor, Read, Seek};
@@ -9,6 +13,16 @@ use std::str::FromStr;
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Ancillary chunk types the PNG specification requires before PLTE and IDAT
+const BEFORE_PLTE_TYPES: [&str; 8] = [
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI",
+];
+
+/// Chunk types the PNG specification requires before IDAT
+const BEFORE_IDAT_TYPES: [&str; 11] = [
+    "PLTE", "bKGD", "hIST", "tRNS", "pHYs", "sPLT", "eXIf", "oFFs", "pCAL", "sCAL", "sTER",
+];
+
 #[derive(Debug)]
 pub struct Png {
     pub signature: [u8; 8],
@@ -31,7 +45,7 @@ impl fmt::Display for PNGError {
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
             signature: Png::STANDARD_HEADER,
             chunks,
@@ -48,17 +62,119 @@ impl Png {
             .find(|chunk| chunk.chunk_type.to_string() == chunk_type)
     }
 
+    /// Returns every chunk of the given type in file order
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        self.chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type.to_string() == chunk_type)
+            .collect()
+    }
+
+    /// Parses the IHDR chunk of the image
+    pub fn header(&self) -> Result<ImageHeader> {
+        let chunk = self
+            .chunk_by_type(ImageHeader::CHUNK_TYPE)
+            .ok_or(HeaderError::Missing("IHDR chunk not found.".to_string()))?;
+
+        ImageHeader::try_from(chunk)
+    }
+
+    /// Concatenates and inflates all IDAT chunks into filtered scanlines
+    pub fn image_data(&self) -> Result<ImageData> {
+        let header = self.header()?;
+        let idat_chunks: Vec<&Chunk> = self
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .collect();
+
+        ImageData::decompress(header, &idat_chunks)
+    }
+
+    /// Unfilters and de-interlaces the image data, keeping the image's own sample format
+    pub fn raw_image(&self) -> Result<RawImage> {
+        RawImage::from_image_data(&self.image_data()?)
+    }
+
+    /// Decodes the image into an RGBA8 or RGBA16 pixel buffer
+    pub fn decode(&self) -> Result<DecodedImage> {
+        let header = self.header()?;
+        let palette = self
+            .chunk_by_type(Palette::CHUNK_TYPE)
+            .map(|chunk| Palette::from_chunk(chunk, &header))
+            .transpose()?;
+        let transparency = self
+            .chunk_by_type(Transparency::CHUNK_TYPE)
+            .map(|chunk| Transparency::from_chunk(chunk, &header, palette.as_ref()))
+            .transpose()?;
+
+        self.raw_image()?
+            .to_rgba(palette.as_ref(), transparency.as_ref())
+    }
+
+    /// Replaces all IDAT chunks with new ones at the position of the first, and rewrites
+    /// IHDR to match the header the new image data was encoded with
+    pub fn replace_image_data(&mut self, header: ImageHeader, idat_chunks: Vec<Chunk>) {
+        let is_idat = |chunk: &Chunk| chunk.chunk_type().to_string() == "IDAT";
+        let index = self
+            .chunks
+            .iter()
+            .position(is_idat)
+            .unwrap_or(self.chunks.len().saturating_sub(1));
+
+        self.chunks.retain(|chunk| !is_idat(chunk));
+        self.chunks.splice(index..index, idat_chunks);
+
+        if let Some(ihdr) = self
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.chunk_type().to_string() == ImageHeader::CHUNK_TYPE)
+        {
+            *ihdr = header.to_chunk();
+        }
+    }
+
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.insert(self.chunks.len() - 1, chunk)
     }
 
+    /// Inserts a chunk where the specification allows it: before PLTE or IDAT for types that
+    /// must precede them, and before IEND for everything else. With `replace`, the first chunk
+    /// of the same type is replaced in place instead, if there is one.
+    pub fn insert_chunk(&mut self, chunk: Chunk, replace: bool) {
+        let existing = self
+            .chunks
+            .iter()
+            .position(|value| value.chunk_type() == chunk.chunk_type());
+        if let (true, Some(index)) = (replace, existing) {
+            self.chunks[index] = chunk;
+            return;
+        }
+
+        let chunk_type = chunk.chunk_type().to_string();
+        let limit: &[&str] = if BEFORE_PLTE_TYPES.contains(&chunk_type.as_str()) {
+            &["PLTE", "IDAT"]
+        } else if BEFORE_IDAT_TYPES.contains(&chunk_type.as_str()) {
+            &["IDAT"]
+        } else {
+            &["IEND"]
+        };
+
+        let index = self
+            .chunks
+            .iter()
+            .position(|value| limit.contains(&value.chunk_type().to_string().as_str()))
+            .unwrap_or(self.chunks.len());
+        self.chunks.insert(index, chunk)
+    }
+
     pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         if let Some(index) = self
             .chunks
             .iter()
             .position(|value| *value.chunk_type() == ChunkType::from_str(chunk_type).unwrap())
         {
-            let chunk = self.chunks.swap_remove(index);
+            let chunk = self.chunks.remove(index);
             Ok(chunk)
         } else {
             Err(ChunkError::NotFound("Chunktype not found.".to_string()).into())
@@ -88,6 +204,10 @@ impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Png::STANDARD_HEADER.len() {
+            let error_message = format!("{} bytes is too short for a PNG file", bytes.len());
+            return Err(PNGError::HeaderError(error_message).into());
+        }
         let header: [u8; 8] = bytes[0..=7].try_into()?;
         let chunks = &bytes[8..];
         let mut cursor = Cursor::new(chunks);
@@ -102,14 +222,24 @@ impl TryFrom<&[u8]> for Png {
             let chunk_length = u32::from_be_bytes(<[u8; 4]>::try_from(buffer.clone()).unwrap());
 
             cursor.seek_relative(-4)?;
-            buffer = vec![0; (chunk_length + 12) as usize];
+            let remaining = chunks.len() as u64 - cursor.position();
+            if chunk_length as u64 + 12 > remaining {
+                break;
+            }
+            buffer = vec![0; chunk_length as usize + 12];
 
             if let Err(_e) = cursor.read_exact(&mut buffer) {
                 break;
             }
 
             let chunk = Chunk::try_from(buffer.as_ref())?;
+            let is_end = chunk.chunk_type().to_string() == "IEND";
             all_chunks.push(chunk);
+
+            // Anything after IEND is not part of the image
+            if is_end {
+                break;
+            }
         }
 
         let png = Png {
@@ -129,7 +259,8 @@ impl TryFrom<&[u8]> for Png {
 mod tests {
     use super::*;
     use crate::chunk::Chunk;
-    use crate::chunk_type::{ChunkType, ChunkTypeError};
+    use crate::chunk_type::ChunkType;
+    use crate::encoder::PngEncoder;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
@@ -234,6 +365,20 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am a second first chunk").unwrap());
+
+        let chunks = png.chunks_by_type("FrSt");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            &chunks[1].data_as_string().unwrap(),
+            "I am a second first chunk"
+        );
+        assert!(png.chunks_by_type("NoNe").is_empty());
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -252,6 +397,142 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_insert_chunk_placement() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "h").unwrap(),
+            chunk_from_strings("PLTE", "p").unwrap(),
+            chunk_from_strings("IDAT", "d").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.insert_chunk(chunk_from_strings("tEXt", "t").unwrap(), false);
+        png.insert_chunk(chunk_from_strings("gAMA", "g").unwrap(), false);
+        png.insert_chunk(chunk_from_strings("tRNS", "r").unwrap(), false);
+        png.insert_chunk(chunk_from_strings("prVt", "x").unwrap(), false);
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "tEXt", "prVt", "IEND"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_png_from_short_input() {
+        assert!(Png::try_from(&[][..]).is_err());
+        assert!(Png::try_from(&PNG_FILE[..7]).is_err());
+    }
+
+    #[test]
+    fn test_png_from_truncated_chunk() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.truncate(bytes.len() - 12);
+        bytes.extend([0xff, 0xff, 0xff, 0xff, b'I', b'D', b'A', b'T']);
+        assert!(Png::try_from(bytes.as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_remove_first_chunk_keeps_order() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        png.insert_chunk(chunk_from_strings("TeSt", "Message").unwrap(), false);
+        png.remove_first_chunk("TeSt").unwrap();
+
+        let remaining: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(remaining, types);
+        assert_eq!(remaining.last().unwrap(), "IEND");
+    }
+
+    #[test]
+    fn test_insert_chunk_replace() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "h").unwrap(),
+            chunk_from_strings("IDAT", "d").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.insert_chunk(chunk_from_strings("pHYs", "Message").unwrap(), true);
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "pHYs");
+
+        png.insert_chunk(chunk_from_strings("pHYs", "Replaced").unwrap(), true);
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(&png.chunks()[1].data_as_string().unwrap(), "Replaced");
+
+        png.insert_chunk(chunk_from_strings("pHYs", "Another").unwrap(), false);
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_header() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let header = png.header().unwrap();
+        assert_eq!((header.width, header.height), (50, 50));
+        assert_eq!(header.bit_depth, 8);
+
+        assert!(testing_png().header().is_err());
+    }
+
+    #[test]
+    fn test_image_data() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let image_data = png.image_data().unwrap();
+        assert_eq!(image_data.stride(), 200);
+        assert_eq!(image_data.raw.len(), 50 * 201);
+        assert_eq!(image_data.scanlines().len(), 50);
+
+        assert!(testing_png().image_data().is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let decoded = png.decode().unwrap();
+        assert_eq!((decoded.width, decoded.height), (50, 50));
+        assert_eq!(decoded.as_bytes().len(), 50 * 50 * 4);
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let raw_image = png.raw_image().unwrap();
+        let idat_chunks = PngEncoder::for_raw_image(&raw_image)
+            .unwrap()
+            .with_idat_chunk_size(1000)
+            .unwrap()
+            .encode_image_data(&raw_image.data)
+            .unwrap();
+        png.replace_image_data(raw_image.header, idat_chunks);
+
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types.iter().filter(|t| *t == "IDAT").count(), 5);
+        assert_eq!(types.first().unwrap(), "IHDR");
+        assert_eq!(types.last().unwrap(), "IEND");
+        assert_eq!(png.raw_image().unwrap(), raw_image);
+    }
+
+    #[test]
+    fn test_ignores_data_after_iend() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"trailing data");
+
+        let png = Png::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -0,0 +1,420 @@
+//! JSON documents printed by `--format json`. Every document is an object with a
+//! `schema_version` field, currently 1. Fields are only ever added within a version; renaming
+//! or removing one bumps it.
+//!
+//! - `print`: `chunks` is a list of chunk objects, `trailing_bytes` counts bytes after IEND.
+//!   A chunk object has `index`, `offset` (byte position of its length field in the file),
+//!   `type`, `critical`, `public`, `reserved_valid`, `safe_to_copy`, `length`, `crc` and
+//!   `content`. `content` is null unless the type is one of IHDR, PLTE, pHYs, gAMA, sRGB, tIME,
+//!   tEXt, zTXt or iTXt and the data parses.
+//! - `decode`: `chunk_type`, `length`, `message` (null when written to a file), `file` (null,
+//!   or `name` and `mime_type`) and `output` (null, or the path written to).
+//! - `dpi get`: `phys` is null, or `pixels_per_unit_x`, `pixels_per_unit_y`, `unit`
+//!   (`"meter"` or `"unknown"`), `dpi_x` and `dpi_y` (null without a unit).
+//! - `idat info`: `header`, `idat_chunks`, `compressed_bytes`, `raw_bytes`, `stride`, `passes`
+//!   and `filters`, a count per filter type keyed `none`, `sub`, `up`, `average` and `paeth`.
+//! - `analyze`: `findings` is a list of `test`, `score` and `details`, `overall` is the
+//!   highest score.
+//! - `verify`: `chunks` is a list of `type`, `index`, `length` and `status` (`"intact"`,
+//!   `"altered"`, `"missing"` or `"added"`), `intact` is true when every chunk is intact.
+//!   The document is printed either way, and the command still fails when `intact` is false.
+//! - `diff`: `changes` is a list with a `change` of `"added"`, `"removed"`, `"replaced"` or
+//!   `"moved"`, plus `index` or `old_index` and `new_index`, `type`, `length` and `crc`.
+//!   Replacements hold `length` and `crc` in `old` and `new` objects instead. `pixels` is
+//!   `"identical"`, `"different"`, `"different_sizes"` or `"undecodable"`.
+//!
+//! Headers are objects with `width`, `height`, `bit_depth`, `color_type` (`"grayscale"`,
+//! `"rgb"`, `"indexed"`, `"grayscale_alpha"` or `"rgba"`) and `interlaced`.
+
+use crate::analyze::Finding;
+use crate::chunk::Chunk;
+use crate::diff::{ChunkChange, ChunkSummary};
+use crate::ihdr::{ColorType, ImageHeader};
+use crate::image_data::ImageData;
+use crate::palette::Palette;
+use crate::payload::FileInfo;
+use crate::phys::{PhysicalDimensions, Unit};
+use crate::png::Png;
+use crate::signature::Coverage;
+use flate2::read::ZlibDecoder;
+use serde_json::{Value, json};
+use std::io::Read;
+use std::path::Path;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Upper bound for inflated zTXt and iTXt text, so a small chunk cannot expand without limit
+const MAX_TEXT_LEN: u64 = 1 << 20;
+
+pub fn chunks(png: &Png, trailing_len: usize) -> Value {
+    let header = png.header().ok();
+    let mut offset = Png::STANDARD_HEADER.len();
+    let chunks: Vec<Value> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let value = chunk_json(index, offset, chunk, header.as_ref());
+            offset += 12 + chunk.length() as usize;
+            value
+        })
+        .collect();
+
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "chunks": chunks,
+        "trailing_bytes": trailing_len,
+    })
+}
+
+fn chunk_json(index: usize, offset: usize, chunk: &Chunk, header: Option<&ImageHeader>) -> Value {
+    let chunk_type = chunk.chunk_type();
+    json!({
+        "index": index,
+        "offset": offset,
+        "type": chunk_type.to_string(),
+        "critical": chunk_type.is_critical(),
+        "public": chunk_type.is_public(),
+        "reserved_valid": chunk_type.is_reserved_bit_valid(),
+        "safe_to_copy": chunk_type.is_safe_to_copy(),
+        "length": chunk.length(),
+        "crc": chunk.crc(),
+        "content": chunk_content(chunk, header),
+    })
+}
+
+/// Decoded data of the chunk types this tool understands, or null
+fn chunk_content(chunk: &Chunk, header: Option<&ImageHeader>) -> Value {
+    let data = chunk.data();
+    match chunk.chunk_type().to_string().as_str() {
+        ImageHeader::CHUNK_TYPE => {
+            ImageHeader::try_from(chunk).map_or(Value::Null, |h| header_json(&h))
+        }
+        Palette::CHUNK_TYPE => header
+            .and_then(|header| Palette::from_chunk(chunk, header).ok())
+            .map_or(
+                Value::Null,
+                |palette| json!({ "entries": palette.entries.len() }),
+            ),
+        PhysicalDimensions::CHUNK_TYPE => {
+            PhysicalDimensions::try_from(chunk).map_or(Value::Null, |phys| phys_json(&phys))
+        }
+        "gAMA" => match <[u8; 4]>::try_from(data) {
+            Ok(bytes) => json!({ "gamma": u32::from_be_bytes(bytes) as f64 / 100_000.0 }),
+            Err(_) => Value::Null,
+        },
+        "sRGB" => match data {
+            [intent] => json!({ "rendering_intent": intent }),
+            _ => Value::Null,
+        },
+        "tIME" => match data {
+            [year_high, year_low, month, day, hour, minute, second] => json!({
+                "timestamp": format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                    u16::from_be_bytes([*year_high, *year_low]),
+                    month,
+                    day,
+                    hour,
+                    minute,
+                    second
+                ),
+            }),
+            _ => Value::Null,
+        },
+        "tEXt" => split_keyword(data).map_or(
+            Value::Null,
+            |(keyword, text)| json!({ "keyword": keyword, "text": latin1(text) }),
+        ),
+        "zTXt" => split_keyword(data)
+            .and_then(|(keyword, rest)| match rest {
+                [0, compressed @ ..] => Some((keyword, inflate(compressed)?)),
+                _ => None,
+            })
+            .map_or(
+                Value::Null,
+                |(keyword, text)| json!({ "keyword": keyword, "text": latin1(&text) }),
+            ),
+        "iTXt" => international_text(data).unwrap_or(Value::Null),
+        _ => Value::Null,
+    }
+}
+
+fn international_text(data: &[u8]) -> Option<Value> {
+    let (keyword, rest) = split_keyword(data)?;
+    let (&[compressed, _method], rest) = rest.split_first_chunk::<2>()?;
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let language = String::from_utf8(rest[..language_end].to_vec()).ok()?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    let translated_keyword = String::from_utf8(rest[..translated_end].to_vec()).ok()?;
+    let text = &rest[translated_end + 1..];
+    let text = match compressed {
+        0 => text.to_vec(),
+        _ => inflate(text)?,
+    };
+
+    Some(json!({
+        "keyword": keyword,
+        "language": language,
+        "translated_keyword": translated_keyword,
+        "text": String::from_utf8(text).ok()?,
+    }))
+}
+
+/// Splits the Latin-1 keyword that text chunks start with from the rest of the data
+fn split_keyword(data: &[u8]) -> Option<(String, &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((latin1(&data[..end]), &data[end + 1..]))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn inflate(compressed: &[u8]) -> Option<Vec<u8>> {
+    let mut text = vec![];
+    ZlibDecoder::new(compressed)
+        .take(MAX_TEXT_LEN)
+        .read_to_end(&mut text)
+        .ok()?;
+    Some(text)
+}
+
+fn header_json(header: &ImageHeader) -> Value {
+    let color_type = match header.color_type {
+        ColorType::Grayscale => "grayscale",
+        ColorType::Rgb => "rgb",
+        ColorType::Indexed => "indexed",
+        ColorType::GrayscaleAlpha => "grayscale_alpha",
+        ColorType::Rgba => "rgba",
+    };
+    json!({
+        "width": header.width,
+        "height": header.height,
+        "bit_depth": header.bit_depth,
+        "color_type": color_type,
+        "interlaced": header.interlaced,
+    })
+}
+
+fn phys_json(phys: &PhysicalDimensions) -> Value {
+    let dpi = phys.dpi();
+    json!({
+        "pixels_per_unit_x": phys.pixels_per_unit_x,
+        "pixels_per_unit_y": phys.pixels_per_unit_y,
+        "unit": match phys.unit {
+            Unit::Meter => "meter",
+            Unit::Unknown => "unknown",
+        },
+        "dpi_x": dpi.map(|(x, _)| x),
+        "dpi_y": dpi.map(|(_, y)| y),
+    })
+}
+
+pub fn phys(phys: Option<&PhysicalDimensions>) -> Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "phys": phys.map(phys_json),
+    })
+}
+
+pub fn image_data(image_data: &ImageData, filter_counts: &[usize; 5]) -> Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "header": header_json(&image_data.header),
+        "idat_chunks": image_data.idat_count,
+        "compressed_bytes": image_data.compressed_len,
+        "raw_bytes": image_data.raw.len(),
+        "stride": image_data.stride(),
+        "passes": image_data.passes().len(),
+        "filters": {
+            "none": filter_counts[0],
+            "sub": filter_counts[1],
+            "up": filter_counts[2],
+            "average": filter_counts[3],
+            "paeth": filter_counts[4],
+        },
+    })
+}
+
+pub fn findings(findings: &[Finding], overall: f64) -> Value {
+    let findings: Vec<Value> = findings
+        .iter()
+        .map(|finding| {
+            json!({
+                "test": finding.test,
+                "score": finding.score,
+                "details": finding.details,
+            })
+        })
+        .collect();
+
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "findings": findings,
+        "overall": overall,
+    })
+}
+
+pub fn coverage(coverage: &[Coverage], intact: bool) -> Value {
+    let chunks: Vec<Value> = coverage
+        .iter()
+        .map(|chunk| {
+            json!({
+                "type": chunk.chunk_type.to_string(),
+                "index": chunk.index,
+                "length": chunk.length,
+                "status": chunk.status.to_string(),
+            })
+        })
+        .collect();
+
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "chunks": chunks,
+        "intact": intact,
+    })
+}
+
+pub fn changes(changes: &[ChunkChange], pixels: &str) -> Value {
+    let changes: Vec<Value> = changes
+        .iter()
+        .map(|change| match change {
+            ChunkChange::Added { index, chunk } => json!({
+                "change": "added",
+                "index": index,
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+                "crc": chunk.crc,
+            }),
+            ChunkChange::Removed { index, chunk } => json!({
+                "change": "removed",
+                "index": index,
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+                "crc": chunk.crc,
+            }),
+            ChunkChange::Replaced { index, old, new } => json!({
+                "change": "replaced",
+                "index": index,
+                "type": new.chunk_type.to_string(),
+                "old": summary_json(old),
+                "new": summary_json(new),
+            }),
+            ChunkChange::Moved {
+                old_index,
+                new_index,
+                chunk,
+            } => json!({
+                "change": "moved",
+                "old_index": old_index,
+                "new_index": new_index,
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+                "crc": chunk.crc,
+            }),
+        })
+        .collect();
+
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "changes": changes,
+        "pixels": pixels,
+    })
+}
+
+fn summary_json(chunk: &ChunkSummary) -> Value {
+    json!({
+        "length": chunk.length,
+        "crc": chunk.crc,
+    })
+}
+
+pub fn decoded(
+    chunk_type: &str,
+    length: usize,
+    message: Option<&str>,
+    file: Option<&FileInfo>,
+    output: Option<&Path>,
+) -> Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "chunk_type": chunk_type,
+        "length": length,
+        "message": message,
+        "file": file.map(|file| json!({ "name": file.name, "mime_type": file.mime_type })),
+        "output": output.map(|path| path.display().to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_chunks_offsets_and_properties() {
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]),
+            chunk("ruSt", b"hidden"),
+            chunk("IEND", &[]),
+        ]);
+        let document = chunks(&png, 3);
+
+        assert_eq!(document["schema_version"], SCHEMA_VERSION);
+        assert_eq!(document["trailing_bytes"], 3);
+        let chunks = document["chunks"].as_array().unwrap();
+        assert_eq!(chunks[0]["offset"], 8);
+        assert_eq!(chunks[1]["offset"], 8 + 12 + 13);
+        assert_eq!(chunks[2]["offset"], 8 + 12 + 13 + 12 + 6);
+        assert_eq!(chunks[0]["content"]["color_type"], "rgba");
+        assert_eq!(chunks[1]["type"], "ruSt");
+        assert_eq!(chunks[1]["critical"], false);
+        assert_eq!(chunks[1]["public"], false);
+        assert_eq!(chunks[1]["safe_to_copy"], true);
+        assert_eq!(chunks[1]["crc"], png.chunks()[1].crc());
+        assert!(chunks[1]["content"].is_null());
+    }
+
+    #[test]
+    fn test_text_content() {
+        let text = chunk_content(&chunk("tEXt", b"Title\0caf\xe9"), None);
+        assert_eq!(text["keyword"], "Title");
+        assert_eq!(text["text"], "café");
+
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(zlib(b"compressed"));
+        let compressed = chunk_content(&chunk("zTXt", &data), None);
+        assert_eq!(compressed["text"], "compressed");
+
+        let mut data = b"Author\0\x01\0en\0Autor\0".to_vec();
+        data.extend(zlib("Jürgen".as_bytes()));
+        let international = chunk_content(&chunk("iTXt", &data), None);
+        assert_eq!(international["language"], "en");
+        assert_eq!(international["translated_keyword"], "Autor");
+        assert_eq!(international["text"], "Jürgen");
+
+        assert!(chunk_content(&chunk("tEXt", b"no separator"), None).is_null());
+    }
+
+    #[test]
+    fn test_time_content() {
+        let time = chunk_content(&chunk("tIME", &[0x07, 0xe8, 2, 29, 13, 5, 9]), None);
+        assert_eq!(time["timestamp"], "2024-02-29T13:05:09Z");
+        assert!(chunk_content(&chunk("tIME", &[0x07]), None).is_null());
+    }
+}
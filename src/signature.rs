@@ -0,0 +1,403 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use core::fmt;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+pub type Error = Box<dyn std::error::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Private, ancillary and unsafe to copy, since it depends on the image data
+pub const CHUNK_TYPE: &str = "siGN";
+/// Chunk types covered by every signature, in addition to the chosen ancillary types
+pub const REQUIRED_TYPES: [&str; 3] = ["IHDR", "PLTE", "IDAT"];
+
+const VERSION: u8 = 1;
+const DOMAIN: &[u8] = b"pngme-signature-v1\0";
+const DIGEST_LEN: usize = 32;
+const ENTRY_LEN: usize = 8 + DIGEST_LEN;
+
+/// Digest of one covered chunk, recorded in the order the chunks appear in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub chunk_type: ChunkType,
+    pub length: u32,
+    pub digest: [u8; DIGEST_LEN],
+}
+
+/// Typed contents of a siGN chunk: the covered chunk types, a digest of every covered chunk
+/// and an Ed25519 signature over both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub covered_types: Vec<ChunkType>,
+    pub entries: Vec<Entry>,
+    pub signature: Signature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Intact,
+    Altered,
+    Missing,
+    Added,
+}
+
+/// How one covered chunk compares with the signed manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub chunk_type: ChunkType,
+    pub index: usize,
+    pub length: u32,
+    pub status: Status,
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    InvalidChunk(String),
+    InvalidType(String),
+    Missing(String),
+    InvalidSignature(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignatureError::InvalidChunk(e) => write!(f, "Signature Error: {}", e),
+            SignatureError::InvalidType(e) => write!(f, "Signature Error: {}", e),
+            SignatureError::Missing(e) => write!(f, "Signature Error: {}", e),
+            SignatureError::InvalidSignature(e) => write!(f, "Signature Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self {
+            Status::Intact => "intact",
+            Status::Altered => "altered",
+            Status::Missing => "missing",
+            Status::Added => "added",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} #{} ({} bytes): {}",
+            self.chunk_type, self.index, self.length, self.status
+        )
+    }
+}
+
+impl Entry {
+    fn for_chunk(chunk: &Chunk) -> Entry {
+        let mut hasher = Sha256::new();
+        hasher.update(chunk.chunk_type().bytes());
+        hasher.update(chunk.length().to_be_bytes());
+        hasher.update(chunk.data());
+
+        Entry {
+            chunk_type: chunk.chunk_type().clone(),
+            length: chunk.length(),
+            digest: hasher.finalize().into(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Signs the required chunk types plus the given ancillary types
+    pub fn sign(png: &Png, key: &SigningKey, ancillary_types: &[ChunkType]) -> Result<Manifest> {
+        let mut covered_types = REQUIRED_TYPES
+            .iter()
+            .map(|chunk_type| ChunkType::from_str(chunk_type))
+            .collect::<Result<Vec<_>>>()?;
+
+        for chunk_type in ancillary_types {
+            if chunk_type.is_critical() || chunk_type.to_string() == CHUNK_TYPE {
+                let error_message = format!("{} is not a signable ancillary chunk", chunk_type);
+                return Err(SignatureError::InvalidType(error_message).into());
+            }
+            if !covered_types.contains(chunk_type) {
+                covered_types.push(chunk_type.clone());
+            }
+        }
+
+        if covered_types.len() > u8::MAX as usize {
+            let error_message = format!("Cannot sign {} chunk types", covered_types.len());
+            return Err(SignatureError::InvalidType(error_message).into());
+        }
+
+        let entries = covered_chunks(png, &covered_types)
+            .map(|(_, chunk)| Entry::for_chunk(chunk))
+            .collect();
+
+        let mut manifest = Manifest {
+            covered_types,
+            entries,
+            signature: Signature::from_bytes(&[0; Signature::BYTE_SIZE]),
+        };
+        manifest.signature = key.sign(&manifest.signed_bytes());
+
+        Ok(manifest)
+    }
+
+    /// Checks the signature, then compares every covered chunk with its recorded digest
+    pub fn verify(&self, png: &Png, key: &VerifyingKey) -> Result<Vec<Coverage>> {
+        key.verify(&self.signed_bytes(), &self.signature)
+            .map_err(|_| {
+                let error_message =
+                    "Signature does not match the public key or the manifest was altered"
+                        .to_string();
+                SignatureError::InvalidSignature(error_message)
+            })?;
+
+        let chunks: Vec<(usize, &Chunk)> = covered_chunks(png, &self.covered_types).collect();
+        let mut coverage = Vec::new();
+
+        for (position, entry) in self.entries.iter().enumerate() {
+            match chunks.get(position) {
+                Some((index, chunk)) => {
+                    let status = if Entry::for_chunk(chunk) == *entry {
+                        Status::Intact
+                    } else {
+                        Status::Altered
+                    };
+                    coverage.push(Coverage {
+                        chunk_type: chunk.chunk_type().clone(),
+                        index: *index,
+                        length: chunk.length(),
+                        status,
+                    });
+                }
+                None => coverage.push(Coverage {
+                    chunk_type: entry.chunk_type.clone(),
+                    index: position,
+                    length: entry.length,
+                    status: Status::Missing,
+                }),
+            }
+        }
+
+        for (index, chunk) in chunks.iter().skip(self.entries.len()) {
+            coverage.push(Coverage {
+                chunk_type: chunk.chunk_type().clone(),
+                index: *index,
+                length: chunk.length(),
+                status: Status::Added,
+            });
+        }
+
+        Ok(coverage)
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = self.body();
+        data.extend_from_slice(&self.signature.to_bytes());
+
+        Ok(Chunk::new(ChunkType::from_str(CHUNK_TYPE)?, data))
+    }
+
+    /// Everything but the signature, in the layout stored in the chunk
+    fn body(&self) -> Vec<u8> {
+        let mut data = vec![VERSION, self.covered_types.len() as u8];
+        for chunk_type in &self.covered_types {
+            data.extend_from_slice(&chunk_type.chunk_type);
+        }
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            data.extend_from_slice(&entry.chunk_type.bytes());
+            data.extend_from_slice(&entry.length.to_be_bytes());
+            data.extend_from_slice(&entry.digest);
+        }
+        data
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        [DOMAIN.to_vec(), self.body()].concat()
+    }
+}
+
+impl TryFrom<&Chunk> for Manifest {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let invalid = || -> Error {
+            let error_message = "siGN chunk is malformed".to_string();
+            SignatureError::InvalidChunk(error_message).into()
+        };
+
+        let data = chunk.data();
+        if data.len() < 2 || data[0] != VERSION {
+            return Err(invalid());
+        }
+
+        let type_count = data[1] as usize;
+        let entries_start = 2 + type_count * 4 + 4;
+        if data.len() < entries_start {
+            return Err(invalid());
+        }
+
+        let covered_types = data[2..2 + type_count * 4]
+            .chunks_exact(4)
+            .map(|bytes| ChunkType::try_from(<[u8; 4]>::try_from(bytes).unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let entry_count =
+            u32::from_be_bytes(data[entries_start - 4..entries_start].try_into().unwrap()) as usize;
+        let entries_end = entry_count
+            .checked_mul(ENTRY_LEN)
+            .and_then(|len| len.checked_add(entries_start))
+            .ok_or_else(invalid)?;
+        if data.len() != entries_end + Signature::BYTE_SIZE {
+            return Err(invalid());
+        }
+
+        let entries = data[entries_start..entries_end]
+            .chunks_exact(ENTRY_LEN)
+            .map(|bytes| {
+                Ok(Entry {
+                    chunk_type: ChunkType::try_from(<[u8; 4]>::try_from(&bytes[..4]).unwrap())?,
+                    length: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+                    digest: bytes[8..].try_into().unwrap(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let signature = Signature::from_slice(&data[entries_end..]).map_err(|_| invalid())?;
+
+        Ok(Manifest {
+            covered_types,
+            entries,
+            signature,
+        })
+    }
+}
+
+/// Reads the manifest from the siGN chunk of a PNG file
+pub fn find_manifest(png: &Png) -> Result<Manifest> {
+    let chunk = png
+        .chunk_by_type(CHUNK_TYPE)
+        .ok_or(SignatureError::Missing("siGN chunk not found.".to_string()))?;
+
+    Manifest::try_from(chunk)
+}
+
+fn covered_chunks<'a>(
+    png: &'a Png,
+    covered_types: &'a [ChunkType],
+) -> impl Iterator<Item = (usize, &'a Chunk)> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| covered_types.contains(chunk.chunk_type()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::PngEncoder;
+    use crate::ihdr::ColorType;
+
+    fn testing_png() -> Png {
+        let mut png = PngEncoder::new(4, 4, ColorType::Rgb, 8)
+            .unwrap()
+            .with_idat_chunk_size(8)
+            .unwrap()
+            .encode(&[7; 48])
+            .unwrap();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"Author\0pngme".to_vec(),
+        ));
+        png
+    }
+
+    fn signed_png(key: &SigningKey) -> Png {
+        let mut png = testing_png();
+        let text = ChunkType::from_str("tEXt").unwrap();
+        let manifest = Manifest::sign(&png, key, &[text]).unwrap();
+        png.append_chunk(manifest.to_chunk().unwrap());
+        png
+    }
+
+    fn statuses(coverage: &[Coverage]) -> Vec<Status> {
+        coverage.iter().map(|c| c.status).collect()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let png = signed_png(&key);
+
+        let manifest = find_manifest(&png).unwrap();
+        assert_eq!(manifest.covered_types.len(), 4);
+        assert_eq!(
+            Manifest::try_from(&manifest.to_chunk().unwrap()).unwrap(),
+            manifest
+        );
+
+        let coverage = manifest.verify(&png, &key.verifying_key()).unwrap();
+        assert!(coverage.len() > 3);
+        assert!(coverage.iter().all(|c| c.status == Status::Intact));
+        assert_eq!(coverage.last().unwrap().chunk_type.to_string(), "tEXt");
+    }
+
+    #[test]
+    fn test_verify_reports_altered_chunks() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let png = signed_png(&key);
+        let manifest = find_manifest(&png).unwrap();
+
+        let mut chunks = png.chunks().to_vec();
+        let text = chunks
+            .iter()
+            .position(|c| c.chunk_type().to_string() == "tEXt")
+            .unwrap();
+        chunks[text] = Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"Author\0someone else".to_vec(),
+        );
+        chunks.insert(text, chunks[text].clone());
+        let tampered = Png::from_chunks(chunks);
+
+        let coverage = manifest.verify(&tampered, &key.verifying_key()).unwrap();
+        let statuses = statuses(&coverage);
+        assert_eq!(
+            statuses[statuses.len() - 2..],
+            [Status::Altered, Status::Added]
+        );
+    }
+
+    #[test]
+    fn test_verify_wrong_key() {
+        let png = signed_png(&SigningKey::from_bytes(&[1; 32]));
+        let manifest = find_manifest(&png).unwrap();
+
+        let other = SigningKey::from_bytes(&[2; 32]);
+        let error = manifest.verify(&png, &other.verifying_key()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<SignatureError>(),
+            Some(SignatureError::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_sign_rejects_critical_types() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let iend = ChunkType::from_str("IEND").unwrap();
+        assert!(Manifest::sign(&testing_png(), &key, &[iend]).is_err());
+    }
+
+    #[test]
+    fn test_malformed_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str(CHUNK_TYPE).unwrap(), vec![1, 0, 0]);
+        assert!(Manifest::try_from(&chunk).is_err());
+    }
+}
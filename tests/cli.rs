@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+
+/// A 1x1 RGBA image made of IHDR, IDAT and IEND chunks
+const PNG_FILE: [u8; 70] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0xf0,
+    0x1f, 0x00, 0x05, 0x00, 0x01, 0xff, 0x89, 0x99, 0x3d, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+    0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pngme-cli-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn pngme(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_pngme"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&[&chunk_type[..], data].concat());
+    [
+        &(data.len() as u32).to_be_bytes()[..],
+        chunk_type,
+        data,
+        &crc.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// The test image with a chunk inserted right before IEND
+fn png_with_chunk(chunk: &[u8]) -> Vec<u8> {
+    let iend = PNG_FILE.len() - 12;
+    [&PNG_FILE[..iend], chunk, &PNG_FILE[iend..]].concat()
+}
+
+#[test]
+fn test_encode_stores_plain_message_as_is() {
+    let dir = temp_dir("plain");
+    let input = dir.join("input.png");
+    let output = dir.join("output.png");
+    fs::write(&input, PNG_FILE).unwrap();
+
+    pngme(&[
+        "encode",
+        input.to_str().unwrap(),
+        "ruSt",
+        "hello",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(
+        fs::read(&output).unwrap(),
+        png_with_chunk(&chunk(b"ruSt", b"hello"))
+    );
+}
+
+#[test]
+fn test_stdout_output_holds_only_the_png() {
+    let dir = temp_dir("stdout");
+    let input = dir.join("input.png");
+    let output = dir.join("output.png");
+    fs::write(&input, png_with_chunk(&chunk(b"ruSt", b"hello"))).unwrap();
+    let input = input.to_str().unwrap();
+    let output = output.to_str().unwrap();
+
+    let stdout = pngme(&["remove", input, "ruSt", "-o", "-"]).stdout;
+    assert_eq!(stdout, PNG_FILE);
+
+    let key = dir.join("key");
+    let key = key.to_str().unwrap();
+    pngme(&["keygen", key, "--signing"]);
+    for args in [
+        vec!["dpi", "set", input, "300"],
+        vec!["sign", input, "--key", key],
+    ] {
+        let stdout = pngme(&[&args[..], &["-o", "-"]].concat()).stdout;
+        pngme(&[&args[..], &["-o", output, "--force"]].concat());
+        assert_eq!(stdout, fs::read(output).unwrap());
+    }
+}